@@ -0,0 +1,18 @@
+#[cfg(feature = "plugin")]
+const COMMANDS: &[&str] = &[
+    "configure_sync",
+    "get_sync_config",
+    "manual_sync",
+    "is_cloud_sync_enabled",
+    "has_legacy_db",
+    "migrate_from_legacy",
+    "list_profiles",
+    "add_profile",
+    "remove_profile",
+    "activate_profile",
+];
+
+fn main() {
+    #[cfg(feature = "plugin")]
+    tauri_plugin::Builder::new(COMMANDS).build();
+}
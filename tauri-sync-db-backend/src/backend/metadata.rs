@@ -0,0 +1,247 @@
+//! Internal metadata tables
+//!
+//! The library owns `sync_status`, `_sync_device`, `_sync_changelog`, `_sync_deletes`,
+//! `_sync_pushed` and `sync_conflicts`. They are created and migrated by `init_db`; the applied
+//! version is kept in `_sync_meta` so the app's own `PRAGMA user_version` stays free.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::profiles::DEFAULT_PROFILE;
+use crate::error::SyncDbError;
+
+pub(crate) const DELETE_LOG_DDL: &str = "CREATE TABLE IF NOT EXISTS _sync_deletes (
+    table_name TEXT NOT NULL,
+    pk TEXT NOT NULL,
+    deleted_at NOT NULL,
+    pending INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (table_name, pk)
+)";
+
+/// Adds the HLC a delete was pushed with, by which other devices pull it. Also run on the remote.
+pub(crate) const DELETE_LOG_HLC_DDL: &str = "ALTER TABLE _sync_deletes ADD COLUMN sync_hlc TEXT";
+
+const CHANGELOG_DDL: &str = "CREATE TABLE IF NOT EXISTS _sync_changelog (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    pk TEXT NOT NULL,
+    op TEXT NOT NULL,
+    changed_at NOT NULL
+);
+CREATE INDEX IF NOT EXISTS _sync_changelog_table ON _sync_changelog (table_name, seq)";
+
+const CONFLICTS_DDL: &str = "CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    pk TEXT NOT NULL,
+    local_row TEXT NOT NULL,
+    remote_row TEXT NOT NULL,
+    detected_at TEXT NOT NULL DEFAULT (datetime('now'))
+)";
+
+const DEVICE_DDL: &str = "CREATE TABLE IF NOT EXISTS _sync_device (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    device_id TEXT NOT NULL,
+    last_hlc TEXT
+);
+INSERT OR IGNORE INTO _sync_device (id, device_id) VALUES (1, lower(hex(randomblob(8))))";
+
+const SYNC_STATUS_DDL: &str = "CREATE TABLE sync_status (
+    table_name TEXT PRIMARY KEY,
+    push_watermark,
+    pull_watermark,
+    sync_count INTEGER NOT NULL DEFAULT 0,
+    last_sync_direction TEXT,
+    last_synced_at TEXT
+)";
+
+/// Whether `table` is one of the library's own tables, which are never synced.
+pub fn is_metadata_table(table: &str) -> bool {
+    let table = table.to_lowercase();
+    table.starts_with("_sync_") || table == "sync_status" || table == "sync_conflicts"
+}
+
+type Migration = fn(&Connection) -> Result<(), SyncDbError>;
+
+/// Metadata migrations, in order. Version N means the first N have been applied.
+const MIGRATIONS: &[Migration] = &[
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+    migrate_v4,
+    migrate_v5,
+];
+
+/// Version of the metadata tables this build expects.
+pub const METADATA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Create or upgrade the metadata tables. Each migration runs in its own transaction.
+pub fn ensure_metadata(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS _sync_meta (key TEXT PRIMARY KEY, value)",
+        [],
+    )?;
+
+    let current = metadata_version(conn)?;
+    if current > METADATA_VERSION {
+        return Err(SyncDbError::Migration { message: format!(
+            "Sync metadata version {} is newer than supported version {}",
+            current, METADATA_VERSION
+        ) });
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = i as i64 + 1;
+        eprintln!("Migrating sync metadata to version {}", version);
+
+        let tx = conn.unchecked_transaction()?;
+        if let Err(e) = migration(&tx) {
+            eprintln!("Sync metadata migration {} failed: {}", version, e);
+            return Err(e);
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO _sync_meta (key, value) VALUES ('schema_version', ?1)",
+            params![version],
+        )?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Currently applied metadata version, 0 for a database that never ran the library.
+pub fn metadata_version(conn: &Connection) -> Result<i64, SyncDbError> {
+    let version: Option<i64> = conn
+        .query_row("SELECT value FROM _sync_meta WHERE key = 'schema_version'", [], |r| r.get(0))
+        .optional()?;
+    Ok(version.unwrap_or(0))
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, SyncDbError> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let names = stmt.query_map([table], |r| r.get(0))?;
+    names.collect::<Result<Vec<String>, _>>().map_err(SyncDbError::from)
+}
+
+/// v1: library-owned tables, and `sync_status` with separate push and pull watermarks.
+///
+/// Apps used to create `sync_status (table_name, last_sync_time, last_sync_direction, sync_count)`
+/// themselves; such a table is rebuilt with `last_sync_time` seeding both watermarks.
+fn migrate_v1(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute_batch(&[DEVICE_DDL, DELETE_LOG_DDL, CHANGELOG_DDL, CONFLICTS_DDL].join(";\n"))?;
+
+    let existing = table_columns(conn, "sync_status")?;
+    if existing.is_empty() {
+        return conn.execute_batch(SYNC_STATUS_DDL).map_err(SyncDbError::from);
+    }
+
+    let pick = |col: &str, fallback: &str| {
+        if existing.iter().any(|c| c == col) { col.to_string() } else { fallback.to_string() }
+    };
+    let watermark = pick("last_sync_time", "NULL");
+
+    conn.execute_batch(&format!(
+        "ALTER TABLE sync_status RENAME TO _sync_status_legacy;
+         {ddl};
+         INSERT OR REPLACE INTO sync_status (table_name, push_watermark, pull_watermark, sync_count, last_sync_direction)
+         SELECT table_name, {watermark}, {watermark}, COALESCE({count}, 0), {direction}
+         FROM _sync_status_legacy WHERE table_name IS NOT NULL;
+         DROP TABLE _sync_status_legacy;",
+        ddl = SYNC_STATUS_DDL,
+        watermark = watermark,
+        count = pick("sync_count", "0"),
+        direction = pick("last_sync_direction", "NULL"),
+    )).map_err(SyncDbError::from)
+}
+
+/// v2: `sync_status.pull_cursor`, the position of an unfinished paged pull.
+fn migrate_v2(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute_batch("ALTER TABLE sync_status ADD COLUMN pull_cursor TEXT").map_err(SyncDbError::from)
+}
+
+/// v3: `sync_status.pull_hlc`, the highest remote HLC pulled, and the HLC of delete log entries.
+fn migrate_v3(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute_batch(&format!("ALTER TABLE sync_status ADD COLUMN pull_hlc TEXT; {}", DELETE_LOG_HLC_DDL))
+        .map_err(SyncDbError::from)
+}
+
+/// v4: `sync_status` keyed by sync profile as well, so each remote keeps its own watermarks.
+/// Existing rows belong to the default profile.
+fn migrate_v4(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute_batch(&format!(
+        "ALTER TABLE sync_status RENAME TO _sync_status_v3;
+         CREATE TABLE sync_status (
+             profile TEXT NOT NULL DEFAULT '{default}',
+             table_name TEXT NOT NULL,
+             push_watermark,
+             pull_watermark,
+             sync_count INTEGER NOT NULL DEFAULT 0,
+             last_sync_direction TEXT,
+             last_synced_at TEXT,
+             pull_cursor TEXT,
+             pull_hlc TEXT,
+             PRIMARY KEY (profile, table_name)
+         );
+         INSERT INTO sync_status (table_name, push_watermark, pull_watermark, sync_count, last_sync_direction, last_synced_at, pull_cursor, pull_hlc)
+         SELECT table_name, push_watermark, pull_watermark, sync_count, last_sync_direction, last_synced_at, pull_cursor, pull_hlc
+         FROM _sync_status_v3;
+         DROP TABLE _sync_status_v3;",
+        default = DEFAULT_PROFILE,
+    )).map_err(SyncDbError::from)
+}
+
+/// v5: `_sync_pushed`, the version (HLC) of each row and delete that a profile's remote has,
+/// so changes pushed to one remote are still pushed to the others.
+/// Nothing is known yet for existing databases, whose first sync pushes every row again.
+fn migrate_v5(conn: &Connection) -> Result<(), SyncDbError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS _sync_pushed (
+             profile TEXT NOT NULL,
+             table_name TEXT NOT NULL,
+             pk TEXT NOT NULL,
+             sync_hlc TEXT NOT NULL,
+             PRIMARY KEY (profile, table_name, pk)
+         )"
+    ).map_err(SyncDbError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_metadata(&conn).unwrap();
+        assert_eq!(metadata_version(&conn).unwrap(), METADATA_VERSION);
+
+        for table in ["sync_status", "_sync_device", "_sync_changelog", "_sync_deletes", "_sync_pushed", "sync_conflicts"] {
+            assert!(!table_columns(&conn, table).unwrap().is_empty(), "{} missing", table);
+        }
+
+        // Running again is a no-op
+        ensure_metadata(&conn).unwrap();
+    }
+
+    #[test]
+    fn test_migrates_app_created_sync_status() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sync_status (table_name TEXT PRIMARY KEY, last_sync_time TEXT, last_sync_direction TEXT, sync_count INTEGER);
+             INSERT INTO sync_status VALUES ('items', '2024-01-02 03:04:05', 'both', 7);"
+        ).unwrap();
+
+        ensure_metadata(&conn).unwrap();
+
+        let (push, pull, count): (String, String, i64) = conn.query_row(
+            "SELECT push_watermark, pull_watermark, sync_count FROM sync_status WHERE table_name = 'items'",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        ).unwrap();
+        assert_eq!(push, "2024-01-02 03:04:05");
+        assert_eq!(pull, "2024-01-02 03:04:05");
+        assert_eq!(count, 7);
+
+        let profile: String = conn.query_row("SELECT profile FROM sync_status WHERE table_name = 'items'", [], |r| r.get(0)).unwrap();
+        assert_eq!(profile, DEFAULT_PROFILE);
+    }
+}
@@ -7,12 +7,37 @@ use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use tauri_plugin_http::reqwest;
-use serde_json::json;
+use crate::error::SyncDbError;
+use crate::remote::RemoteDb;
+use crate::secrets::{EncryptedFileStore, SecretStore};
+use crate::migrations::{run_migrations, Migrations};
+use crate::value::{Row, SyncValue};
+
+pub mod metadata;
+pub mod profiles;
+
+use profiles::{load_profiles, profile_token, save_profiles, token_secret, DEFAULT_PROFILE};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncConfig {
     pub url: String,
     pub token: String,
+    /// Profile the connection belongs to; pass it as [`SyncOptions::profile`](crate::sync::SyncOptions::profile).
+    #[serde(default = "default_profile")]
+    pub profile: String,
+}
+
+/// The active sync connection without its token, for the UI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncConfigInfo {
+    pub url: String,
+    pub profile: String,
+    /// Whether a token is stored for the profile.
+    pub has_token: bool,
+}
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
 }
 
 #[derive(Clone)]
@@ -20,37 +45,60 @@ pub struct DbState {
     // Use tokio Mutex for async compatibility
     pub conn: Arc<Mutex<Option<Connection>>>,
     pub db_path: PathBuf,
+    /// Held for the duration of a sync, so two syncs never run on the same database at once.
+    pub sync_lock: Arc<Mutex<()>>,
+    /// Where the sync token is kept. Defaults to an [`EncryptedFileStore`] next to the database.
+    pub secrets: Arc<dyn SecretStore>,
 }
 
 impl DbState {
     pub fn new(db_path: PathBuf) -> Self {
+        let dir = db_path.parent().map(Path::to_path_buf).unwrap_or_default();
         Self {
             conn: Arc::new(Mutex::new(None)),
             db_path,
+            sync_lock: Arc::new(Mutex::new(())),
+            secrets: Arc::new(EncryptedFileStore::in_dir(&dir)),
         }
     }
 
-    pub async fn get_connection(&self) -> Result<tokio::sync::MutexGuard<'_, Option<Connection>>, String> {
+    /// Keep the sync token in `secrets`, e.g. the platform keychain.
+    pub fn with_secret_store(mut self, secrets: Arc<dyn SecretStore>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub async fn get_connection(&self) -> Result<tokio::sync::MutexGuard<'_, Option<Connection>>, SyncDbError> {
         let guard = self.conn.lock().await;
         if guard.is_none() {
-            return Err("Database not initialized".to_string());
+            return Err(SyncDbError::NotInitialized);
         }
         Ok(guard)
     }
     
     /// Check if cloud sync is enabled (checked by presence of config)
     pub fn is_cloud_sync_enabled(&self) -> bool {
-        get_sync_config(&self.db_path).is_some()
+        self.sync_config().is_some()
+    }
+
+    /// Sync config with the token from this state's secret store.
+    pub fn sync_config(&self) -> Option<SyncConfig> {
+        load_config(self)
     }
 }
 
 /// Initialize database connection
-pub async fn init_db(db_path: &PathBuf) -> Result<DbState, String> {
+pub async fn init_db(db_path: &PathBuf) -> Result<DbState, SyncDbError> {
+    init_db_with_migrations(db_path, &Migrations::default()).await
+}
+
+/// Initialize the database and bring the app schema up to date with `migrations`.
+pub async fn init_db_with_migrations(db_path: &PathBuf, migrations: &Migrations) -> Result<DbState, SyncDbError> {
     eprintln!("Initializing DB at: {:?}", db_path);
 
     // Create directory if not exists
     if let Some(parent) = db_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        fs::create_dir_all(parent)?;
     }
 
     // Try to open connection - this can fail if DB is malformed
@@ -61,11 +109,11 @@ pub async fn init_db(db_path: &PathBuf) -> Result<DbState, String> {
             eprintln!("Failed to open DB connection: {}", err_msg);
              
             // Diagnostic for open failure
-            if let Ok(metadata) = std::fs::metadata(db_path) {
-                return Err(format!("DB Open Failed: {}. File size: {} bytes. Image might be malformed.", err_msg, metadata.len()));
-            } else {
-                return Err(format!("DB Open Failed: {}. File does not exist or inaccessible.", err_msg));
-            }
+            return Err(SyncDbError::MalformedDb {
+                path: db_path.clone(),
+                message: err_msg,
+                file_size: std::fs::metadata(db_path).ok().map(|m| m.len()),
+            });
         }
     };
     
@@ -79,71 +127,174 @@ pub async fn init_db(db_path: &PathBuf) -> Result<DbState, String> {
         eprintln!("Failed to set PRAGMAs: {}", err_msg);
         
         // Detailed diagnostics
-        let metadata = std::fs::metadata(db_path).map_err(|e| e.to_string())?;
+        let metadata = std::fs::metadata(db_path)?;
         eprintln!("DB File size: {} bytes", metadata.len());
         
-        if metadata.len() > 0 {
+        let message = if metadata.len() > 0 {
              let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))
                 .unwrap_or_else(|e| format!("Could not run integrity check: {}", e));
-             
-             return Err(format!("DB init failed: {}. Integrity check: {}. File size: {}", err_msg, integrity, metadata.len()));
+             format!("{}. Integrity check: {}", err_msg, integrity)
         } else {
-             return Err(format!("DB init failed: {}. File is empty.", err_msg));
-        }
+             format!("{}. File is empty", err_msg)
+        };
+        return Err(SyncDbError::MalformedDb { path: db_path.clone(), message, file_size: Some(metadata.len()) });
     }
 
-    let state = DbState {
-        conn: Arc::new(Mutex::new(Some(conn))),
-        db_path: db_path.clone(),
-    };
+    if let Err(e) = metadata::ensure_metadata(&conn) {
+        eprintln!("Failed to prepare sync metadata: {}", e);
+        return Err(e);
+    }
+
+    if let Err(e) = run_migrations(&conn, migrations) {
+        eprintln!("Failed to migrate database: {}", e);
+        return Err(e);
+    }
+
+    let state = DbState::new(db_path.clone());
+    *state.conn.lock().await = Some(conn);
     
     Ok(state)
 }
 
 /// Initialize local-only database (same as init_db for Rusqlite)
-pub async fn init_local_only(db_path: &PathBuf) -> Result<DbState, String> {
+pub async fn init_local_only(db_path: &PathBuf) -> Result<DbState, SyncDbError> {
     init_db(db_path).await
 }
 
-pub fn load_config(db_path: &Path) -> Option<SyncConfig> {
-    let config_path = db_path.parent()?.join("sync_config.json");
-    if config_path.exists() {
-        let content = fs::read_to_string(config_path).ok()?;
-        serde_json::from_str(&content).ok()
-    } else {
-        None
+/// Copy the rows of `tables` from an older database file into `state`, e.g. after the app
+/// moved its database. Only columns present in both databases are copied and existing rows
+/// are kept. The legacy file is renamed to `<name>.migrated` afterwards, so the import runs once.
+/// Returns the number of rows copied.
+pub async fn import_legacy_db(state: &DbState, legacy_path: &Path, tables: &[&str]) -> Result<usize, SyncDbError> {
+    if !legacy_path.exists() {
+        return Err(SyncDbError::Io { message: format!("Legacy database not found: {}", legacy_path.display()) });
     }
+    eprintln!("Importing legacy database {:?}", legacy_path);
+
+    let copied = {
+        let conn_guard = state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        conn.execute("ATTACH DATABASE ?1 AS legacy", [legacy_path.to_string_lossy()])?;
+        let result = copy_legacy_tables(conn, tables);
+        if let Err(e) = conn.execute_batch("DETACH DATABASE legacy") {
+            eprintln!("Warning: failed to detach legacy database: {}", e);
+        }
+        let copied = result?;
+        eprintln!("Imported {} rows from legacy database", copied);
+        copied
+    };
+
+    let mut migrated = legacy_path.as_os_str().to_owned();
+    migrated.push(".migrated");
+    fs::rename(legacy_path, &migrated).map_err(|e| SyncDbError::Io {
+        message: format!("Imported, but could not rename legacy database: {}", e),
+    })?;
+    Ok(copied)
 }
 
-pub async fn configure_sync(db_path: &Path, url: String, token: String) -> Result<(), String> {
-    let config = SyncConfig { url: url.clone(), token: token.clone() };
-    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
-    
-    if let Some(parent) = db_path.parent() {
-        let config_path = parent.join("sync_config.json");
-        fs::write(config_path, json).map_err(|e| e.to_string())?;
-        
-        // Also validate connection if possible
-        let _ = validate_cloud_connection(url, token).await; 
-        
-        Ok(())
-    } else {
-        Err("Invalid database path".to_string())
+fn copy_legacy_tables(conn: &Connection, tables: &[&str]) -> Result<usize, SyncDbError> {
+    let tx = conn.unchecked_transaction()?;
+    let mut copied = 0;
+    for &table in tables {
+        let mut stmt = tx.prepare(
+            "SELECT l.name FROM pragma_table_info(?1, 'legacy') l JOIN pragma_table_info(?1, 'main') m ON m.name = l.name"
+        )?;
+        let columns = stmt.query_map([table], |r| r.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if columns.is_empty() {
+            eprintln!("Legacy database has no table {}, skipping", table);
+            continue;
+        }
+
+        let columns = columns.join(", ");
+        copied += tx.execute(
+            &format!("INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM legacy.{table}"),
+            [],
+        ).map_err(|e| SyncDbError::Sql { message: format!("Failed to import {}: {}", table, e), sql: None, statement_index: None })?;
     }
+    tx.commit()?;
+    Ok(copied)
 }
 
-pub fn get_sync_config(db_path: &PathBuf) -> Option<SyncConfig> {
-    load_config(db_path)
+/// Load the connection of the active sync profile, reading its token from `state.secrets`.
+/// A config of an older version is migrated first, moving its plaintext token into the store.
+pub fn load_config(state: &DbState) -> Option<SyncConfig> {
+    if let Err(e) = profiles::migrate_config(state) {
+        eprintln!("Failed to migrate sync config: {}", e);
+    }
+    let profiles = match load_profiles(&state.db_path) {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            eprintln!("Failed to load sync profiles: {}", e);
+            return None;
+        }
+    };
+    let profile = profiles.active_profile()?;
+    match profile_token(state, &profile.name) {
+        Ok(Some(token)) => Some(SyncConfig { url: profile.url.clone(), token, profile: profile.name.clone() }),
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Failed to read sync token: {}", e);
+            None
+        }
+    }
+}
+
+/// Save the connection of the active sync profile, creating the default profile when there is
+/// none: the URL to `sync_config.json`, the token to `state.secrets`. An empty `token` keeps the
+/// stored one, so a UI that never sees the token can change the URL alone.
+pub async fn configure_sync(state: &DbState, url: String, token: String) -> Result<(), SyncDbError> {
+    let mut profiles = load_profiles(&state.db_path)?;
+    let name = profiles.active.clone().unwrap_or_else(default_profile);
+    profiles.upsert(&name, url.clone());
+    let token = if token.is_empty() {
+        profile_token(state, &name)?.unwrap_or_default()
+    } else {
+        state.secrets.set(&token_secret(&name), &token)?;
+        token
+    };
+    save_profiles(state, &profiles)?;
+
+    // Also validate connection if possible
+    let _ = validate_cloud_connection(url, token).await;
+
+    Ok(())
+}
+
+/// The active sync connection without its token, safe to hand to the webview.
+pub fn get_sync_config(state: &DbState) -> Option<SyncConfigInfo> {
+    let profiles = load_profiles(&state.db_path)
+        .map_err(|e| eprintln!("Failed to load sync profiles: {}", e))
+        .ok()?;
+    let profile = profiles.active_profile()?;
+    Some(SyncConfigInfo {
+        url: profile.url.clone(),
+        profile: profile.name.clone(),
+        has_token: matches!(profile_token(state, &profile.name), Ok(Some(_))),
+    })
 }
 
-pub fn execute_sql(conn: &Connection, sql: &str) -> Result<(), String> {
-    conn.execute(sql, ()).map_err(|e| e.to_string())?;
+pub fn execute_sql(conn: &Connection, sql: &str) -> Result<(), SyncDbError> {
+    conn.execute(sql, ())?;
     Ok(())
 }
 
-/// Query and return rows as vector of optional strings
-pub fn query_strings(conn: &Connection, sql: &str) -> Result<Vec<Vec<Option<String>>>, String> {
-    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+/// Query and return rows as typed values, binding `params` positionally.
+pub fn query_rows(conn: &Connection, sql: &str, params: &[SyncValue]) -> Result<Vec<Row>, SyncDbError> {
+    let mut stmt = conn.prepare(sql)?;
+    let column_count = stmt.column_count();
+
+    let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+        (0..column_count).map(|i| row.get::<_, SyncValue>(i)).collect::<Result<Row, _>>()
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(SyncDbError::from)
+}
+
+/// Query and return rows as vector of optional strings.
+/// Lossy for blobs and numeric types; prefer [`query_rows`].
+pub fn query_strings(conn: &Connection, sql: &str) -> Result<Vec<Vec<Option<String>>>, SyncDbError> {
+    let mut stmt = conn.prepare(sql)?;
     let column_count = stmt.column_count();
     
     // Map each row to a Vec<Option<String>>
@@ -161,39 +312,73 @@ pub fn query_strings(conn: &Connection, sql: &str) -> Result<Vec<Vec<Option<Stri
             row_vec.push(val);
         }
         Ok(row_vec)
-    }).map_err(|e| e.to_string())?;
+    })?;
     
     let mut results = Vec::new();
     for r in rows {
-        results.push(r.map_err(|e| e.to_string())?);
+        results.push(r?);
     }
     
     Ok(results)
 }
 
-/// Validate connection to Turso (Cloud)
-pub async fn validate_cloud_connection(url: String, token: String) -> Result<(), String> {
+/// Validate connection to Turso (Cloud). A rejected token fails with [`SyncDbError::Auth`].
+pub async fn validate_cloud_connection(url: String, token: String) -> Result<(), SyncDbError> {
     eprintln!("Validating cloud connection to {}", url);
-    let http_url = url.replace("libsql://", "https://");
-    
-    let client = reqwest::Client::new();
-    
+    let remote = RemoteDb::new(reqwest::Client::new(), &url, &token);
+
     // Simple query to check connection
-    let body = json!({
-        "statements": ["SELECT 1"]
-    });
-    
-    let res = client.post(&http_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&body).map_err(|e| format!("Serialization failed: {}", e))?)
-        .send()
-        .await
-        .map_err(|e| format!("Network request failed: {}", e))?;
-        
-    if !res.status().is_success() {
-        return Err(format!("Auth failed: {}", res.status()));
-    }
-    
+    remote.execute("SELECT 1".into()).await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profiles::TOKEN_SECRET;
+
+    const CONFIG_FILE: &str = "sync_config.json";
+
+    #[test]
+    fn test_copy_legacy_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "ATTACH DATABASE ':memory:' AS legacy;
+             CREATE TABLE main.notes (id INTEGER PRIMARY KEY, title TEXT, updated_at TEXT);
+             CREATE TABLE legacy.notes (id INTEGER PRIMARY KEY, title TEXT, obsolete TEXT);
+             INSERT INTO main.notes VALUES (1, 'kept', NULL);
+             INSERT INTO legacy.notes VALUES (1, 'old', 'x'), (2, 'imported', 'y');"
+        ).unwrap();
+
+        assert_eq!(copy_legacy_tables(&conn, &["notes", "missing"]).unwrap(), 1);
+        let titles: Vec<String> = conn.prepare("SELECT title FROM main.notes ORDER BY id").unwrap()
+            .query_map([], |r| r.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(titles, vec!["kept", "imported"]);
+    }
+
+    #[test]
+    fn test_plaintext_token_moves_to_secret_store() {
+        let db_path = crate::test_util::temp_db_path("config");
+        let dir = db_path.parent().unwrap();
+        fs::create_dir_all(dir).unwrap();
+        let legacy = r#"{ "url": "libsql://db.turso.io", "token": "legacy-token" }"#;
+        fs::write(dir.join(CONFIG_FILE), legacy).unwrap();
+
+        // The first read migrates the file
+        let state = DbState::new(db_path.clone());
+        let config = state.sync_config().unwrap();
+        assert_eq!((config.url.as_str(), config.token.as_str()), ("libsql://db.turso.io", "legacy-token"));
+        assert_eq!(config.profile, DEFAULT_PROFILE);
+        assert!(!fs::read_to_string(dir.join(CONFIG_FILE)).unwrap().contains("legacy-token"));
+        assert_eq!(state.secrets.get(TOKEN_SECRET).unwrap().as_deref(), Some("legacy-token"));
+        assert_eq!(state.sync_config().unwrap().token, "legacy-token");
+
+        // The UI learns that a token is set, not the token
+        let info = get_sync_config(&state).unwrap();
+        assert_eq!((info.profile.as_str(), info.has_token), (DEFAULT_PROFILE, true));
+        assert!(!serde_json::to_string(&info).unwrap().contains("legacy-token"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
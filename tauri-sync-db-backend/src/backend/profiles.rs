@@ -0,0 +1,258 @@
+//! Named sync profiles
+//!
+//! `sync_config.json` lists the remotes a database can sync with and which one is active:
+//!
+//! ```json
+//! { "active": "team", "profiles": [{ "name": "personal", "url": "libsql://..." }, { "name": "team", "url": "libsql://..." }] }
+//! ```
+//!
+//! Tokens are kept in the [`SecretStore`](crate::secrets::SecretStore) under [`token_secret`].
+//! Each profile has its own rows
+//! in `sync_status` and `_sync_pushed`, so switching remotes doesn't mix up their watermarks
+//! and a change pushed to one remote is still pushed to the others.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use super::DbState;
+use crate::error::SyncDbError;
+use crate::secrets::write_private;
+
+/// Profile of configs written before profiles existed, and of `sync_status` rows migrated from them.
+pub const DEFAULT_PROFILE: &str = "default";
+/// Name of the default profile's token in the secret store.
+pub const TOKEN_SECRET: &str = "turso_token";
+
+const CONFIG_FILE: &str = "sync_config.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncProfile {
+    pub name: String,
+    pub url: String,
+}
+
+/// Contents of `sync_config.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncProfiles {
+    /// Profile synced with; `None` when sync is disabled.
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub profiles: Vec<SyncProfile>,
+}
+
+impl SyncProfiles {
+    pub fn get(&self, name: &str) -> Option<&SyncProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn active_profile(&self) -> Option<&SyncProfile> {
+        self.get(self.active.as_deref()?)
+    }
+
+    /// Add `name` or change its URL, activating it if no profile is active.
+    /// Returns whether an existing profile now points at another remote.
+    pub(crate) fn upsert(&mut self, name: &str, url: String) -> bool {
+        let moved = match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(profile) => {
+                let moved = profile.url != url;
+                profile.url = url;
+                moved
+            }
+            None => {
+                self.profiles.push(SyncProfile { name: name.to_string(), url });
+                false
+            }
+        };
+        self.active.get_or_insert_with(|| name.to_string());
+        moved
+    }
+}
+
+/// `sync_config.json` as written by any version.
+#[derive(Default, Deserialize)]
+struct StoredConfig {
+    #[serde(flatten)]
+    profiles: SyncProfiles,
+    /// Single remote of configs written before profiles.
+    url: Option<String>,
+    /// Plaintext token of configs written before the secret store.
+    token: Option<String>,
+}
+
+/// Name of a profile's token in the secret store.
+pub fn token_secret(profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        TOKEN_SECRET.to_string()
+    } else {
+        format!("{}:{}", TOKEN_SECRET, profile)
+    }
+}
+
+fn config_path(db_path: &Path) -> Result<PathBuf, SyncDbError> {
+    let dir = db_path.parent().ok_or_else(|| config_error("Invalid database path".to_string()))?;
+    Ok(dir.join(CONFIG_FILE))
+}
+
+fn config_error(message: String) -> SyncDbError {
+    SyncDbError::Config { message }
+}
+
+fn check_name(name: &str) -> Result<(), SyncDbError> {
+    if name.trim().is_empty() {
+        return Err(config_error("Sync profile name must not be empty".to_string()));
+    }
+    Ok(())
+}
+
+fn read_config(db_path: &Path) -> Result<StoredConfig, SyncDbError> {
+    let path = config_path(db_path)?;
+    if !path.exists() {
+        return Ok(StoredConfig::default());
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content).map_err(|e| config_error(format!("Invalid {}: {}", CONFIG_FILE, e)))
+}
+
+/// Read `sync_config.json`. The single remote of a config written before profiles is the
+/// default profile.
+pub fn load_profiles(db_path: &Path) -> Result<SyncProfiles, SyncDbError> {
+    let stored = read_config(db_path)?;
+    let mut profiles = stored.profiles;
+    if let Some(url) = stored.url {
+        if profiles.get(DEFAULT_PROFILE).is_none() {
+            profiles.upsert(DEFAULT_PROFILE, url);
+        }
+    }
+    Ok(profiles)
+}
+
+/// Token of `profile` from `state.secrets`, falling back to the plaintext token of a config
+/// written before the secret store if [`migrate_config`] could not move it.
+pub(crate) fn profile_token(state: &DbState, profile: &str) -> Result<Option<String>, SyncDbError> {
+    if let Some(token) = state.secrets.get(&token_secret(profile))? {
+        return Ok(Some(token));
+    }
+    if profile != DEFAULT_PROFILE {
+        return Ok(None);
+    }
+    Ok(read_config(&state.db_path)?.token)
+}
+
+/// Rewrite a config of an older version in the current format, moving its plaintext token into
+/// `state.secrets`. [`load_config`](super::load_config) and every change of the profiles do this
+/// too; call it at startup to get the token off disk right away.
+pub fn migrate_config(state: &DbState) -> Result<(), SyncDbError> {
+    let stored = read_config(&state.db_path)?;
+    if stored.url.is_none() && stored.token.is_none() {
+        return Ok(());
+    }
+    save_profiles(state, &load_profiles(&state.db_path)?)
+}
+
+pub(crate) fn save_profiles(state: &DbState, profiles: &SyncProfiles) -> Result<(), SyncDbError> {
+    // The rewrite drops a plaintext token, unless a newer one is stored already
+    if let Some(token) = read_config(&state.db_path)?.token {
+        let name = token_secret(DEFAULT_PROFILE);
+        if state.secrets.get(&name)?.is_none() {
+            state.secrets.set(&name, &token)?;
+            eprintln!("Moved sync token from {} into the secret store", CONFIG_FILE);
+        }
+    }
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| config_error(e.to_string()))?;
+    write_private(&config_path(&state.db_path)?, json.as_bytes())
+}
+
+pub fn list_profiles(state: &DbState) -> Result<SyncProfiles, SyncDbError> {
+    load_profiles(&state.db_path)
+}
+
+/// Add a profile, or replace the URL and token of an existing one. The first profile added
+/// becomes active. A profile pointed at another remote starts over with fresh watermarks.
+pub async fn add_profile(state: &DbState, name: &str, url: String, token: String) -> Result<(), SyncDbError> {
+    check_name(name)?;
+    let mut profiles = list_profiles(state)?;
+    if profiles.upsert(name, url) {
+        clear_sync_status(state, name).await?;
+    }
+    state.secrets.set(&token_secret(name), &token)?;
+    save_profiles(state, &profiles)
+}
+
+/// Remove a profile with its token and sync state. Removing the active profile disables sync.
+pub async fn remove_profile(state: &DbState, name: &str) -> Result<(), SyncDbError> {
+    let mut profiles = list_profiles(state)?;
+    if profiles.get(name).is_none() {
+        return Err(config_error(format!("No sync profile named '{}'", name)));
+    }
+    clear_sync_status(state, name).await?;
+    profiles.profiles.retain(|p| p.name != name);
+    if profiles.active.as_deref() == Some(name) {
+        profiles.active = None;
+    }
+    save_profiles(state, &profiles)?;
+    state.secrets.delete(&token_secret(name))
+}
+
+/// Sync with `name` from now on.
+pub fn activate_profile(state: &DbState, name: &str) -> Result<(), SyncDbError> {
+    let mut profiles = list_profiles(state)?;
+    if profiles.get(name).is_none() {
+        return Err(config_error(format!("No sync profile named '{}'", name)));
+    }
+    profiles.active = Some(name.to_string());
+    save_profiles(state, &profiles)
+}
+
+async fn clear_sync_status(state: &DbState, profile: &str) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    conn.execute("DELETE FROM sync_status WHERE profile = ?1", params![profile])?;
+    conn.execute("DELETE FROM _sync_pushed WHERE profile = ?1", params![profile])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::init_db;
+    use crate::test_util::temp_db_path;
+
+    #[tokio::test]
+    async fn test_add_activate_and_remove_profiles() {
+        let db_path = temp_db_path("profiles");
+        let state = init_db(&db_path).await.unwrap();
+        add_profile(&state, "personal", "libsql://personal".into(), "p-token".into()).await.unwrap();
+        add_profile(&state, "team", "libsql://team".into(), "t-token".into()).await.unwrap();
+        assert_eq!(state.sync_config().unwrap().token, "p-token");
+
+        activate_profile(&state, "team").unwrap();
+        let config = state.sync_config().unwrap();
+        assert_eq!((config.profile.as_str(), config.url.as_str()), ("team", "libsql://team"));
+        assert!(activate_profile(&state, "staging").is_err());
+
+        // Pointing a profile at another remote drops its watermarks
+        {
+            let conn_guard = state.get_connection().await.unwrap();
+            conn_guard.as_ref().unwrap().execute_batch(
+                "INSERT INTO sync_status (profile, table_name, pull_watermark) VALUES ('personal', 'notes', 5), ('team', 'notes', 7)"
+            ).unwrap();
+        }
+        add_profile(&state, "personal", "libsql://elsewhere".into(), "p-token".into()).await.unwrap();
+        let remaining = {
+            let conn_guard = state.get_connection().await.unwrap();
+            crate::backend::query_strings(conn_guard.as_ref().unwrap(), "SELECT profile FROM sync_status").unwrap()
+        };
+        assert_eq!(remaining, vec![vec![Some("team".to_string())]]);
+
+        remove_profile(&state, "team").await.unwrap();
+        assert!(state.sync_config().is_none());
+        assert_eq!(state.secrets.get(&token_secret("team")).unwrap(), None);
+        let profiles = list_profiles(&state).unwrap();
+        assert_eq!((profiles.active, profiles.profiles.len()), (None, 1));
+        fs::remove_dir_all(db_path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Conflict resolution
+//!
+//! A conflict is a row changed locally and, by another device, on the remote since
+//! the last sync. `SyncSchema::conflict_resolver` picks the policy per table;
+//! conflicts a policy leaves unresolved are stored in `sync_conflicts` for the app.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::DbState;
+use crate::error::SyncDbError;
+use crate::value::{Row, SyncValue};
+
+/// Which version of a column (or row) to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Local,
+    Remote,
+    /// The version with the newer HLC.
+    Newer,
+}
+
+/// Both versions of a conflicting row.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub table: String,
+    /// Column names, in the order of the values in `local` and `remote`.
+    pub columns: Vec<String>,
+    pub local: Row,
+    pub remote: Row,
+    /// Whether the remote version has the newer HLC.
+    pub remote_is_newer: bool,
+}
+
+impl Conflict {
+    pub fn local_value(&self, column: &str) -> Option<&SyncValue> {
+        self.columns.iter().position(|c| c == column).map(|i| &self.local[i])
+    }
+
+    pub fn remote_value(&self, column: &str) -> Option<&SyncValue> {
+        self.columns.iter().position(|c| c == column).map(|i| &self.remote[i])
+    }
+}
+
+/// Outcome of resolving a conflict.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Local,
+    Remote,
+    /// A new version of the row, in the column order of the conflict.
+    /// It is written locally and pushed with a fresh HLC.
+    Merged(Row),
+    /// Keep the local row without pushing it and record the conflict in `sync_conflicts`.
+    Unresolved,
+}
+
+pub type ConflictCallback = Arc<dyn Fn(&Conflict) -> Resolution + Send + Sync>;
+
+/// Policy for conflicting rows of a table.
+#[derive(Clone, Default)]
+pub enum ConflictResolver {
+    /// The version with the newer HLC wins.
+    #[default]
+    LastWriterWins,
+    LocalWins,
+    RemoteWins,
+    /// Build a merged row column by column; unlisted columns come from the newer version.
+    PerColumn(HashMap<String, Side>),
+    /// Custom merge receiving both versions.
+    Callback(ConflictCallback),
+}
+
+impl ConflictResolver {
+    pub fn callback(f: impl Fn(&Conflict) -> Resolution + Send + Sync + 'static) -> Self {
+        ConflictResolver::Callback(Arc::new(f))
+    }
+
+    pub fn resolve(&self, conflict: &Conflict) -> Resolution {
+        let newer = if conflict.remote_is_newer { Resolution::Remote } else { Resolution::Local };
+        match self {
+            ConflictResolver::LastWriterWins => newer,
+            ConflictResolver::LocalWins => Resolution::Local,
+            ConflictResolver::RemoteWins => Resolution::Remote,
+            ConflictResolver::PerColumn(sides) => {
+                let merged: Row = conflict.columns.iter().enumerate().map(|(i, col)| {
+                    let take_remote = match sides.get(col).copied().unwrap_or(Side::Newer) {
+                        Side::Local => false,
+                        Side::Remote => true,
+                        Side::Newer => conflict.remote_is_newer,
+                    };
+                    if take_remote { conflict.remote[i].clone() } else { conflict.local[i].clone() }
+                }).collect();
+
+                if merged == conflict.local {
+                    Resolution::Local
+                } else if merged == conflict.remote {
+                    Resolution::Remote
+                } else {
+                    Resolution::Merged(merged)
+                }
+            }
+            ConflictResolver::Callback(f) => f(conflict),
+        }
+    }
+}
+
+impl std::fmt::Debug for ConflictResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictResolver::LastWriterWins => write!(f, "LastWriterWins"),
+            ConflictResolver::LocalWins => write!(f, "LocalWins"),
+            ConflictResolver::RemoteWins => write!(f, "RemoteWins"),
+            ConflictResolver::PerColumn(sides) => f.debug_tuple("PerColumn").field(sides).finish(),
+            ConflictResolver::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// A conflict stored in `sync_conflicts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictRecord {
+    pub id: i64,
+    pub table_name: String,
+    /// Primary key values as a JSON array.
+    pub pk: String,
+    /// Local version as a JSON object of column to value.
+    pub local_row: String,
+    /// Remote version as a JSON object of column to value.
+    pub remote_row: String,
+    pub detected_at: String,
+}
+
+fn row_json(columns: &[String], row: &Row) -> String {
+    let obj: serde_json::Map<String, serde_json::Value> = columns.iter().zip(row.iter())
+        .map(|(c, v)| (c.clone(), v.to_json()))
+        .collect();
+    serde_json::Value::Object(obj).to_string()
+}
+
+pub(crate) fn record_conflict(conn: &Connection, conflict: &Conflict, pk: &str) -> Result<(), SyncDbError> {
+    conn.execute(
+        "INSERT INTO sync_conflicts (table_name, pk, local_row, remote_row) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            conflict.table,
+            pk,
+            row_json(&conflict.columns, &conflict.local),
+            row_json(&conflict.columns, &conflict.remote),
+        ],
+    )?;
+    Ok(())
+}
+
+/// List unresolved conflicts, oldest first.
+pub async fn load_conflicts(state: &DbState) -> Result<Vec<ConflictRecord>, SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, table_name, pk, local_row, remote_row, detected_at FROM sync_conflicts ORDER BY id"
+    )?;
+    let rows = stmt.query_map([], |r| Ok(ConflictRecord {
+        id: r.get(0)?,
+        table_name: r.get(1)?,
+        pk: r.get(2)?,
+        local_row: r.get(3)?,
+        remote_row: r.get(4)?,
+        detected_at: r.get(5)?,
+    }))?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(SyncDbError::from)
+}
+
+/// Remove a conflict once the app has dealt with it.
+/// Editing the local row afterwards pushes it on the next sync.
+pub async fn dismiss_conflict(state: &DbState, id: i64) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    conn.execute("DELETE FROM sync_conflicts WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(remote_is_newer: bool) -> Conflict {
+        Conflict {
+            table: "notes".into(),
+            columns: vec!["id".into(), "title".into(), "body".into()],
+            local: vec![SyncValue::Integer(1), "local title".into(), "local body".into()],
+            remote: vec![SyncValue::Integer(1), "remote title".into(), "remote body".into()],
+            remote_is_newer,
+        }
+    }
+
+    #[test]
+    fn test_builtin_resolvers() {
+        assert_eq!(ConflictResolver::LastWriterWins.resolve(&conflict(true)), Resolution::Remote);
+        assert_eq!(ConflictResolver::LastWriterWins.resolve(&conflict(false)), Resolution::Local);
+        assert_eq!(ConflictResolver::LocalWins.resolve(&conflict(true)), Resolution::Local);
+        assert_eq!(ConflictResolver::RemoteWins.resolve(&conflict(false)), Resolution::Remote);
+    }
+
+    #[test]
+    fn test_per_column_merge() {
+        let resolver = ConflictResolver::PerColumn(HashMap::from([("title".to_string(), Side::Local)]));
+        let merged = resolver.resolve(&conflict(true));
+        assert_eq!(
+            merged,
+            Resolution::Merged(vec![SyncValue::Integer(1), "local title".into(), "remote body".into()])
+        );
+
+        // Everything from the newer side is just that side
+        assert_eq!(resolver.resolve(&conflict(false)), Resolution::Local);
+    }
+
+    #[test]
+    fn test_callback_and_record() {
+        let resolver = ConflictResolver::callback(|c| {
+            if c.local_value("body") == c.remote_value("body") { Resolution::Local } else { Resolution::Unresolved }
+        });
+        let c = conflict(true);
+        assert_eq!(resolver.resolve(&c), Resolution::Unresolved);
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::backend::metadata::ensure_metadata(&conn).unwrap();
+        record_conflict(&conn, &c, "[1]").unwrap();
+        let local_row: String = conn.query_row("SELECT local_row FROM sync_conflicts", [], |r| r.get(0)).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&local_row).unwrap();
+        assert_eq!(parsed["title"], "local title");
+    }
+}
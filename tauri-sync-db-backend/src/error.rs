@@ -0,0 +1,199 @@
+//! Errors of the public API
+//!
+//! [`SyncDbError`] is returned by every fallible function of the crate and by the plugin
+//! commands. It serializes as an object with a `kind`, a human readable `message` and
+//! the variant's context, so a Tauri frontend can branch on `kind` and still show `message`:
+//!
+//! ```json
+//! { "kind": "auth", "message": "Auth failed (HTTP 401): invalid token", "status": 401 }
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::remote::{RemoteError, RemoteErrorKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncDbError {
+    /// The database connection is not open: `init_db` did not run or failed.
+    NotInitialized,
+    /// The database file can't be opened or is corrupt.
+    MalformedDb { path: PathBuf, message: String, file_size: Option<u64> },
+    /// Migrations are invalid or newer than supported, or a remote migration statement failed.
+    /// Local migration failures keep the kind of their error.
+    Migration { message: String },
+    /// A file could not be read or written.
+    Io { message: String },
+    /// The remote rejected the token (HTTP 401/403).
+    Auth { status: u16, message: String },
+    /// The remote could not be reached or failed transiently: connection errors, HTTP 429, 5xx.
+    Network { status: Option<u16>, message: String },
+    /// A statement failed. `statement_index` is its position in the remote request it was sent in.
+    Sql { message: String, sql: Option<String>, statement_index: Option<usize> },
+    /// The remote answered with something that is not a valid response.
+    Protocol { status: Option<u16>, message: String },
+    /// The local and remote schema of the synced tables can't be reconciled.
+    Schema { message: String },
+    /// Sync is not configured, or the sync config, profiles or secret store are invalid.
+    Config { message: String },
+    /// Another sync is running on the same database.
+    SyncInProgress,
+    /// The sync was cancelled through its `CancelToken`.
+    Cancelled,
+    Other { message: String },
+}
+
+impl SyncDbError {
+    /// Name of the variant, as serialized in `kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SyncDbError::NotInitialized => "not_initialized",
+            SyncDbError::MalformedDb { .. } => "malformed_db",
+            SyncDbError::Migration { .. } => "migration",
+            SyncDbError::Io { .. } => "io",
+            SyncDbError::Auth { .. } => "auth",
+            SyncDbError::Network { .. } => "network",
+            SyncDbError::Sql { .. } => "sql",
+            SyncDbError::Protocol { .. } => "protocol",
+            SyncDbError::Schema { .. } => "schema",
+            SyncDbError::Config { .. } => "config",
+            SyncDbError::SyncInProgress => "sync_in_progress",
+            SyncDbError::Cancelled => "cancelled",
+            SyncDbError::Other { .. } => "other",
+        }
+    }
+
+    /// HTTP status returned by the remote, if the error came from one.
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            SyncDbError::Auth { status, .. } => Some(*status),
+            SyncDbError::Network { status, .. } | SyncDbError::Protocol { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SyncDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncDbError::NotInitialized => f.write_str("Database not initialized"),
+            SyncDbError::MalformedDb { path, message, file_size } => {
+                write!(f, "Database {} can't be opened: {}", path.display(), message)?;
+                match file_size {
+                    Some(size) => write!(f, ". File size: {} bytes", size),
+                    None => f.write_str(". File does not exist or is inaccessible"),
+                }
+            }
+            SyncDbError::Migration { message } => write!(f, "Migration failed: {}", message),
+            SyncDbError::Io { message } => write!(f, "I/O error: {}", message),
+            SyncDbError::Auth { status, message } => write!(f, "Auth failed (HTTP {}): {}", status, message),
+            SyncDbError::Network { status: Some(status), message } => write!(f, "Remote unavailable (HTTP {}): {}", status, message),
+            SyncDbError::Network { status: None, message } => write!(f, "Cloud connection failed: {}", message),
+            SyncDbError::Sql { message, sql, statement_index } => {
+                f.write_str(message)?;
+                match (statement_index, sql) {
+                    (Some(i), Some(sql)) => write!(f, " (in statement {}: {})", i, sql),
+                    (None, Some(sql)) => write!(f, " (in statement: {})", sql),
+                    _ => Ok(()),
+                }
+            }
+            SyncDbError::Protocol { status: Some(status), message } => write!(f, "Remote request failed with HTTP {}: {}", status, message),
+            SyncDbError::Protocol { status: None, message } => write!(f, "Invalid remote response: {}", message),
+            SyncDbError::Schema { message } => write!(f, "Remote schema is incompatible: {}", message),
+            SyncDbError::Config { message } => f.write_str(message),
+            SyncDbError::SyncInProgress => f.write_str("A sync is already in progress"),
+            SyncDbError::Cancelled => f.write_str("Sync cancelled"),
+            SyncDbError::Other { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for SyncDbError {}
+
+impl Serialize for SyncDbError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            SyncDbError::MalformedDb { path, file_size, .. } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("file_size", file_size)?;
+            }
+            SyncDbError::Auth { status, .. } => map.serialize_entry("status", status)?,
+            SyncDbError::Network { status, .. } | SyncDbError::Protocol { status, .. } => map.serialize_entry("status", status)?,
+            SyncDbError::Sql { sql, statement_index, .. } => {
+                map.serialize_entry("sql", sql)?;
+                map.serialize_entry("statement_index", statement_index)?;
+            }
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+impl From<RemoteError> for SyncDbError {
+    fn from(e: RemoteError) -> Self {
+        let kind = e.kind();
+        match e {
+            RemoteError::Network(message) => SyncDbError::Network { status: None, message },
+            RemoteError::Http { status, body, .. } => match kind {
+                RemoteErrorKind::Auth => SyncDbError::Auth { status, message: body },
+                RemoteErrorKind::RateLimited | RemoteErrorKind::Server => SyncDbError::Network { status: Some(status), message: body },
+                _ => SyncDbError::Protocol { status: Some(status), message: body },
+            },
+            RemoteError::Protocol(message) => SyncDbError::Protocol { status: None, message },
+            RemoteError::Statement { message, sql, index } => SyncDbError::Sql { message, sql: Some(sql), statement_index: index },
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SyncDbError {
+    fn from(e: rusqlite::Error) -> Self {
+        SyncDbError::Sql { message: e.to_string(), sql: None, statement_index: None }
+    }
+}
+
+impl From<std::io::Error> for SyncDbError {
+    fn from(e: std::io::Error) -> Self {
+        SyncDbError::Io { message: e.to_string() }
+    }
+}
+
+impl From<SyncDbError> for String {
+    fn from(e: SyncDbError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_errors_keep_their_context() {
+        let auth = SyncDbError::from(RemoteError::Http { status: 401, body: "invalid token".into(), retry_after: None });
+        assert_eq!(auth, SyncDbError::Auth { status: 401, message: "invalid token".into() });
+        assert_eq!(auth.http_status(), Some(401));
+
+        let busy = SyncDbError::from(RemoteError::Http { status: 503, body: String::new(), retry_after: None });
+        assert_eq!(busy.kind(), "network");
+
+        let sql = SyncDbError::from(RemoteError::Statement { message: "no such table: x".into(), sql: "SELECT 1".into(), index: Some(2) });
+        assert_eq!(sql.to_string(), "no such table: x (in statement 2: SELECT 1)");
+    }
+
+    #[test]
+    fn test_serializes_kind_message_and_context() {
+        let error = SyncDbError::Sql { message: "boom".into(), sql: None, statement_index: Some(3) };
+        assert_eq!(serde_json::to_value(&error).unwrap(), serde_json::json!({
+            "kind": "sql",
+            "message": "boom",
+            "sql": null,
+            "statement_index": 3,
+        }));
+        assert_eq!(serde_json::to_value(SyncDbError::NotInitialized).unwrap()["message"], "Database not initialized");
+    }
+}
@@ -0,0 +1,216 @@
+//! Hybrid logical clock
+//!
+//! Rows carry an HLC (wall-clock millis + logical counter + device id) in the
+//! `sync_hlc` column. HLCs only move forward on a device, even when its wall clock
+//! is skewed or jumps back, and they order writes across devices independently
+//! of time zones and of the type used for `updated_at`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection};
+
+use crate::error::SyncDbError;
+
+/// Column holding the HLC of the last write to a row.
+pub const HLC_COLUMN: &str = "sync_hlc";
+
+/// A single timestamp. The string form is fixed-width, so it sorts like the timestamp itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hlc {
+    /// Physical component, UTC milliseconds since the epoch.
+    pub millis: i64,
+    /// Logical component, orders events within the same millisecond.
+    pub counter: u32,
+    /// Device that generated the timestamp; breaks ties between devices.
+    pub device_id: String,
+}
+
+impl Hlc {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, ':');
+        let millis = parts.next()?.parse().ok()?;
+        let counter = parts.next()?.parse().ok()?;
+        let device_id = parts.next()?.to_string();
+        Some(Self { millis, counter, device_id })
+    }
+}
+
+impl fmt::Display for Hlc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:015}:{:010}:{}", self.millis, self.counter, self.device_id)
+    }
+}
+
+impl Ord for Hlc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.millis, self.counter, &self.device_id).cmp(&(other.millis, other.counter, &other.device_id))
+    }
+}
+
+impl PartialOrd for Hlc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Source of the physical component of HLCs, in UTC milliseconds since the epoch.
+#[derive(Clone)]
+pub struct WallClock(Arc<dyn Fn() -> i64 + Send + Sync>);
+
+impl WallClock {
+    pub fn new(f: impl Fn() -> i64 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// The system clock.
+    pub fn system() -> Self {
+        Self::new(|| chrono::Utc::now().timestamp_millis())
+    }
+
+    fn millis(&self) -> i64 {
+        (self.0)()
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+impl fmt::Debug for WallClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WallClock")
+    }
+}
+
+/// Generates HLCs for one device.
+pub struct HlcClock {
+    device_id: String,
+    /// Last issued or observed (millis, counter).
+    last: Mutex<(i64, u32)>,
+    wall: WallClock,
+}
+
+impl HlcClock {
+    pub fn new(device_id: impl Into<String>) -> Self {
+        Self { device_id: device_id.into(), last: Mutex::new((0, 0)), wall: WallClock::system() }
+    }
+
+    /// Read wall-clock time from `wall` instead of the system clock.
+    pub fn with_wall_clock(mut self, wall: WallClock) -> Self {
+        self.wall = wall;
+        self
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Load the device identity and the last persisted clock value from `_sync_device`.
+    pub fn load(conn: &Connection) -> Result<Self, SyncDbError> {
+        let (device_id, last_hlc): (String, Option<String>) = conn
+            .query_row("SELECT device_id, last_hlc FROM _sync_device WHERE id = 1", [], |r| Ok((r.get(0)?, r.get(1)?)))?;
+
+        let clock = Self::new(device_id);
+        if let Some(last) = last_hlc.as_deref().and_then(Hlc::parse) {
+            *clock.last.lock().unwrap() = (last.millis, last.counter);
+        }
+        Ok(clock)
+    }
+
+    /// Persist the clock so timestamps keep increasing across restarts.
+    pub fn persist(&self, conn: &Connection) -> Result<(), SyncDbError> {
+        let (millis, counter) = *self.last.lock().unwrap();
+        let last = Hlc { millis, counter, device_id: self.device_id.clone() };
+        conn.execute("UPDATE _sync_device SET last_hlc = ?1 WHERE id = 1", params![last.to_string()])?;
+        Ok(())
+    }
+
+    /// Timestamp for a local write.
+    pub fn now(&self) -> Hlc {
+        self.tick(self.wall.millis())
+    }
+
+    /// Merge a timestamp received from another device, so later local writes order after it.
+    pub fn observe(&self, remote: &Hlc) {
+        self.merge(remote, self.wall.millis());
+    }
+
+    fn tick(&self, wall: i64) -> Hlc {
+        let mut last = self.last.lock().unwrap();
+        *last = if wall > last.0 { (wall, 0) } else { (last.0, last.1 + 1) };
+        Hlc { millis: last.0, counter: last.1, device_id: self.device_id.clone() }
+    }
+
+    fn merge(&self, remote: &Hlc, wall: i64) {
+        let mut last = self.last.lock().unwrap();
+        let millis = wall.max(last.0).max(remote.millis);
+        let counter = if millis == last.0 && millis == remote.millis {
+            last.1.max(remote.counter) + 1
+        } else if millis == last.0 {
+            last.1 + 1
+        } else if millis == remote.millis {
+            remote.counter + 1
+        } else {
+            0
+        };
+        *last = (millis, counter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_order_matches_hlc_order() {
+        let a = Hlc { millis: 999, counter: 12, device_id: "b".into() };
+        let b = Hlc { millis: 1000, counter: 0, device_id: "a".into() };
+        let c = Hlc { millis: 1000, counter: 0, device_id: "b".into() };
+        assert!(a < b && b < c);
+        assert!(a.to_string() < b.to_string() && b.to_string() < c.to_string());
+        assert_eq!(Hlc::parse(&c.to_string()), Some(c));
+    }
+
+    #[test]
+    fn test_monotonic_when_wall_clock_goes_back() {
+        let clock = HlcClock::new("dev");
+        let first = clock.tick(5_000);
+        let second = clock.tick(1_000);
+        let third = clock.tick(1_000);
+        assert!(first < second && second < third);
+        assert_eq!(second.millis, 5_000);
+    }
+
+    #[test]
+    fn test_observe_orders_later_writes_after_remote() {
+        let clock = HlcClock::new("slow");
+        let remote = Hlc { millis: 10_000, counter: 7, device_id: "fast".into() };
+        clock.merge(&remote, 2_000);
+        let local = clock.tick(2_500);
+        assert!(local > remote);
+    }
+
+    #[test]
+    fn test_wall_clock_is_injectable() {
+        let clock = HlcClock::new("dev").with_wall_clock(WallClock::new(|| 42));
+        assert_eq!(clock.now().to_string(), "000000000000042:0000000000:dev");
+        assert_eq!(clock.now().counter, 1);
+    }
+
+    #[test]
+    fn test_load_and_persist() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::backend::metadata::ensure_metadata(&conn).unwrap();
+        let clock = HlcClock::load(&conn).unwrap();
+        let issued = clock.tick(i64::MAX / 2);
+        clock.persist(&conn).unwrap();
+
+        let reloaded = HlcClock::load(&conn).unwrap();
+        assert_eq!(reloaded.device_id(), clock.device_id());
+        assert!(reloaded.now() > issued);
+    }
+}
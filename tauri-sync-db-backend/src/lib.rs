@@ -6,9 +6,38 @@
 //! Native-only crate (not compiled for WASM).
 
 pub mod backend;
+pub mod conflict;
+pub mod error;
+pub mod hlc;
+pub mod migrations;
+#[cfg(feature = "plugin")]
+pub mod plugin;
+pub mod progress;
+pub mod remote;
+mod remote_schema;
+pub mod report;
+pub mod retry;
+pub mod scheduler;
+pub mod secrets;
 pub mod sync;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod value;
 
 // Re-export commonly used types
-pub use backend::{DbState, SyncConfig, init_db, init_local_only, configure_sync, get_sync_config, validate_cloud_connection, load_config, execute_sql, query_strings};
-pub use sync::{SyncSchema, sync_all};
+pub use backend::{DbState, SyncConfig, SyncConfigInfo, init_db, init_db_with_migrations, init_local_only, import_legacy_db, configure_sync, get_sync_config, validate_cloud_connection, load_config, execute_sql, query_rows, query_strings};
+pub use backend::profiles::{SyncProfile, SyncProfiles, DEFAULT_PROFILE, list_profiles, add_profile, remove_profile, activate_profile, migrate_config};
+pub use backend::metadata::{ensure_metadata, is_metadata_table, metadata_version, METADATA_VERSION};
+pub use sync::{SyncSchema, DynamicSchema, TableFilter, SyncOptions, DEFAULT_PULL_PAGE_SIZE, DEFAULT_PUSH_BATCH_SIZE, ChangeTracking, sync_all, sync_all_with_options, install_delete_log, install_changelog, install_hlc_columns};
+pub use error::SyncDbError;
+pub use conflict::{Conflict, ConflictRecord, ConflictResolver, Resolution, Side, load_conflicts, dismiss_conflict};
+pub use hlc::{Hlc, HlcClock, WallClock};
+pub use migrations::{Migration, Migrations, run_migrations, apply_remote_migrations};
+pub use progress::{CancelToken, ProgressCallback, SyncPhase, SyncProgress};
+pub use remote::{RemoteDb, RemoteError, RemoteErrorKind, RemoteStatement, RemoteTransaction, StmtResult};
+pub use report::{ForeignKeyViolation, SyncReport, TableReport};
+pub use retry::RetryPolicy;
+pub use scheduler::{SchedulerConfig, SyncScheduler};
+pub use secrets::{EncryptedFileStore, SecretStore};
+pub use value::{Row, SyncValue};
 
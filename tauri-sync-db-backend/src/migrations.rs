@@ -0,0 +1,329 @@
+//! App schema migrations
+//!
+//! Ordered, versioned migrations applied by [`init_db_with_migrations`](crate::backend::init_db_with_migrations).
+//! The local schema version is `PRAGMA user_version`; the remote records applied versions
+//! in `_sync_migrations`, so both sides can be brought to the same schema before syncing.
+
+use std::fmt;
+use std::sync::Arc;
+
+use rusqlite::Connection;
+
+use crate::error::SyncDbError;
+use crate::remote::{RemoteDb, RemoteError, RemoteStatement};
+use crate::value::SyncValue;
+
+/// `_sync_meta` key of the remote and migration version the remote was last migrated to.
+pub(crate) const REMOTE_MIGRATIONS_KEY: &str = "remote_migrations";
+
+const REMOTE_MIGRATIONS_DDL: &str = "CREATE TABLE IF NOT EXISTS _sync_migrations (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+)";
+
+pub type MigrationFn = Arc<dyn Fn(&Connection) -> Result<(), SyncDbError> + Send + Sync>;
+
+#[derive(Clone)]
+enum Step {
+    Sql(String),
+    Closure(MigrationFn),
+}
+
+/// A single schema change.
+#[derive(Clone)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    step: Step,
+}
+
+impl Migration {
+    /// One or more SQL statements separated by `;`. Applied locally and, when enabled, on the remote.
+    pub fn sql(version: i64, name: &str, sql: &str) -> Self {
+        Self { version, name: name.to_string(), step: Step::Sql(sql.to_string()) }
+    }
+
+    /// Custom code, e.g. a data backfill. Only applied locally; the remote just records the version.
+    /// Its error is returned by [`run_migrations`] as is.
+    pub fn closure(
+        version: i64,
+        name: &str,
+        f: impl Fn(&Connection) -> Result<(), SyncDbError> + Send + Sync + 'static,
+    ) -> Self {
+        Self { version, name: name.to_string(), step: Step::Closure(Arc::new(f)) }
+    }
+}
+
+impl fmt::Debug for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.step {
+            Step::Sql(_) => "sql",
+            Step::Closure(_) => "closure",
+        };
+        write!(f, "Migration({}, {:?}, {})", self.version, self.name, kind)
+    }
+}
+
+/// The app's migrations, in increasing version order starting at 1.
+#[derive(Debug, Clone, Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+
+    /// Version reached once every migration is applied.
+    pub fn latest_version(&self) -> i64 {
+        self.migrations.last().map(|m| m.version).unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<(), SyncDbError> {
+        let mut previous = 0;
+        for m in &self.migrations {
+            if m.version <= previous {
+                return Err(SyncDbError::Migration { message: format!(
+                    "Migration {} ({}) must have a version greater than {}", m.version, m.name, previous
+                ) });
+            }
+            previous = m.version;
+        }
+        Ok(())
+    }
+
+    fn pending(&self, applied: i64) -> impl Iterator<Item = &Migration> {
+        self.migrations.iter().filter(move |m| m.version > applied)
+    }
+}
+
+/// Apply pending migrations to the local database, each in its own transaction.
+/// Returns the number applied. A failing migration is rolled back and its error returned:
+/// [`SyncDbError::Sql`] carrying the script of a SQL migration, or the closure's own error.
+pub fn run_migrations(conn: &Connection, migrations: &Migrations) -> Result<usize, SyncDbError> {
+    migrations.validate()?;
+
+    let current: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if current > migrations.latest_version() {
+        eprintln!(
+            "Warning: database schema version {} is newer than the latest migration {}",
+            current, migrations.latest_version()
+        );
+    }
+
+    let mut applied = 0;
+    for m in migrations.pending(current) {
+        eprintln!("Applying migration {} ({})", m.version, m.name);
+        let tx = conn.unchecked_transaction()?;
+        let result = match &m.step {
+            Step::Sql(sql) => tx.execute_batch(sql).map_err(|e| SyncDbError::Sql {
+                message: e.to_string(),
+                sql: Some(sql.clone()),
+                statement_index: None,
+            }),
+            Step::Closure(f) => f(&tx),
+        };
+        if let Err(e) = result {
+            eprintln!("Migration {} ({}) failed: {}", m.version, m.name, e);
+            return Err(e);
+        }
+        tx.pragma_update(None, "user_version", m.version)?;
+        tx.commit()?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+/// Apply pending SQL migrations to the remote, recording them in its `_sync_migrations` table.
+/// Each migration and its record are sent as one batch. Returns the number applied.
+pub async fn apply_remote_migrations(remote: &RemoteDb, migrations: &Migrations) -> Result<usize, SyncDbError> {
+    migrations.validate()?;
+    remote.execute(REMOTE_MIGRATIONS_DDL.into()).await?;
+
+    let rows = remote.query("SELECT COALESCE(MAX(version), 0) FROM _sync_migrations".into()).await?;
+    let current = match rows.first().and_then(|r| r.first()) {
+        Some(SyncValue::Integer(v)) => *v,
+        _ => 0,
+    };
+
+    let mut applied = 0;
+    for m in migrations.pending(current) {
+        eprintln!("Applying migration {} ({}) to remote", m.version, m.name);
+        let mut statements: Vec<RemoteStatement> = match &m.step {
+            Step::Sql(sql) => split_statements(sql).into_iter().map(RemoteStatement::from).collect(),
+            Step::Closure(_) => Vec::new(),
+        };
+        statements.push(RemoteStatement::new(
+            "INSERT INTO _sync_migrations (version, name) VALUES (?, ?)",
+            vec![SyncValue::Integer(m.version), SyncValue::Text(m.name.clone())],
+        ));
+        remote.execute_transaction(statements).await.map_err(|e| match e {
+            // Connection and auth failures keep their kind, a failing statement is the migration's fault
+            RemoteError::Statement { .. } => SyncDbError::Migration {
+                message: format!("Remote migration {} ({}) failed: {}", m.version, m.name, e),
+            },
+            e => e.into(),
+        })?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+/// Split a script into single statements for the remote, which takes one statement per entry.
+/// Semicolons inside quotes, comments and trigger bodies don't end a statement.
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                for q in chars.by_ref() {
+                    current.push(q);
+                    if q == close {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for q in chars.by_ref() {
+                    if q == '\n' {
+                        current.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for q in chars.by_ref() {
+                    if prev == '*' && q == '/' {
+                        break;
+                    }
+                    prev = q;
+                }
+                current.push(' ');
+            }
+            ';' => pieces.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    pieces.push(current);
+
+    // Re-join trigger bodies, which contain `;` up to their closing END
+    let mut statements: Vec<String> = Vec::new();
+    let mut in_trigger = false;
+    for piece in pieces {
+        let trimmed = piece.trim();
+        if trimmed.is_empty() && !in_trigger {
+            continue;
+        }
+        if in_trigger {
+            let last = statements.last_mut().expect("trigger start was pushed");
+            last.push(';');
+            last.push_str(&piece);
+        } else {
+            statements.push(trimmed.to_string());
+            let words: Vec<String> = trimmed.split_whitespace().take(3).map(|w| w.to_uppercase()).collect();
+            in_trigger = words.first().is_some_and(|w| w == "CREATE")
+                && words.iter().skip(1).any(|w| w == "TRIGGER");
+        }
+        if in_trigger {
+            let last = statements.last_mut().expect("trigger start was pushed");
+            if last.trim_end().to_uppercase().ends_with("END") {
+                *last = last.trim().to_string();
+                in_trigger = false;
+            }
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations() -> Migrations {
+        Migrations::new()
+            .with(Migration::sql(1, "create notes", "CREATE TABLE notes (id INTEGER PRIMARY KEY, title TEXT);"))
+            .with(Migration::sql(2, "add body", "ALTER TABLE notes ADD COLUMN body TEXT"))
+            .with(Migration::closure(3, "backfill", |conn| {
+                conn.execute("UPDATE notes SET body = '' WHERE body IS NULL", [])?;
+                Ok(())
+            }))
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_run_migrations() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run_migrations(&conn, &migrations()).unwrap(), 3);
+        assert_eq!(user_version(&conn), 3);
+        assert_eq!(run_migrations(&conn, &migrations()).unwrap(), 0);
+
+        let next = migrations().with(Migration::sql(4, "index", "CREATE INDEX notes_title ON notes (title)"));
+        assert_eq!(run_migrations(&conn, &next).unwrap(), 1);
+        assert_eq!(user_version(&conn), 4);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let broken = migrations().with(Migration::sql(4, "broken", "CREATE TABLE tags (id INTEGER); SELECT * FROM missing"));
+        let err = run_migrations(&conn, &broken).unwrap_err();
+        assert!(matches!(&err, SyncDbError::Sql { sql: Some(sql), .. } if sql.contains("missing")), "{:?}", err);
+        assert_eq!(user_version(&conn), 3);
+
+        let refused = SyncDbError::Config { message: "refused".into() };
+        let failing = refused.clone();
+        let broken = migrations().with(Migration::closure(4, "refuse", move |_| Err(failing.clone())));
+        assert_eq!(run_migrations(&conn, &broken).unwrap_err(), refused);
+        let tags: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = 'tags'", [], |r| r.get(0)).unwrap();
+        assert_eq!(tags, 0);
+    }
+
+    #[test]
+    fn test_versions_must_increase() {
+        let conn = Connection::open_in_memory().unwrap();
+        let unordered = Migrations::new()
+            .with(Migration::sql(2, "b", "SELECT 1"))
+            .with(Migration::sql(1, "a", "SELECT 1"));
+        assert!(run_migrations(&conn, &unordered).is_err());
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let sql = "CREATE TABLE t (a TEXT DEFAULT 'x;y'); -- comment; here
+            CREATE TRIGGER t_ins AFTER INSERT ON t BEGIN
+                UPDATE t SET a = 'z' WHERE rowid = NEW.rowid;
+                SELECT 1;
+            END;
+            /* block; comment */ INSERT INTO t VALUES ('end');";
+        let statements = split_statements(sql);
+        assert_eq!(statements.len(), 3, "{:#?}", statements);
+        assert_eq!(statements[0], "CREATE TABLE t (a TEXT DEFAULT 'x;y')");
+        assert!(statements[1].starts_with("CREATE TRIGGER") && statements[1].ends_with("END"));
+        assert_eq!(statements[2], "INSERT INTO t VALUES ('end')");
+
+        // Every piece is a complete statement
+        let conn = Connection::open_in_memory().unwrap();
+        for stmt in statements {
+            conn.execute_batch(&stmt).unwrap();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Tauri plugin
+//!
+//! Registers the commands used by `tauri-sync-db-frontend` against the app's managed
+//! [`DbState`]. The app registers the plugin once and grants `sync-db:default`:
+//!
+//! ```ignore
+//! let db_state = init_db(&db_path).await?;
+//! tauri::Builder::default()
+//!     .manage(db_state)
+//!     .plugin(tauri_sync_db_backend::plugin::Builder::new(MySchema).build())
+//! ```
+//!
+//! Commands are invoked as `plugin:sync-db|<command>` and fail with a serialized
+//! [`SyncDbError`]. Progress of `manual_sync` is emitted as [`PROGRESS_EVENT`] with a
+//! [`SyncProgress`](crate::progress::SyncProgress) payload.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tauri::plugin::{Builder as PluginBuilder, TauriPlugin};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri_plugin_http::reqwest;
+
+use crate::backend::profiles::{self, SyncProfiles};
+use crate::backend::{self, DbState, SyncConfigInfo};
+use crate::conflict::ConflictResolver;
+use crate::error::SyncDbError;
+use crate::progress::ProgressCallback;
+use crate::report::SyncReport;
+use crate::sync::{sync_all_with_options, SyncOptions, SyncSchema};
+
+/// Name of the plugin, the prefix of its commands and permissions.
+pub const PLUGIN_NAME: &str = "sync-db";
+
+/// Event carrying sync progress.
+pub const PROGRESS_EVENT: &str = "sync-db://progress";
+
+#[derive(Clone)]
+struct SharedSchema(Arc<dyn SyncSchema + Send + Sync>);
+
+impl SyncSchema for SharedSchema {
+    fn tables(&self) -> Vec<&str> {
+        self.0.tables()
+    }
+
+    fn get_columns(&self, table: &str) -> Vec<&str> {
+        self.0.get_columns(table)
+    }
+
+    fn get_pks(&self, table: &str) -> Vec<&str> {
+        self.0.get_pks(table)
+    }
+
+    fn get_column_type(&self, table: &str, col: &str) -> Option<String> {
+        self.0.get_column_type(table, col)
+    }
+
+    fn conflict_resolver(&self, table: &str) -> ConflictResolver {
+        self.0.conflict_resolver(table)
+    }
+
+    fn parents(&self, table: &str) -> Option<Vec<&str>> {
+        self.0.parents(table)
+    }
+}
+
+/// State managed by the plugin.
+struct SyncPlugin {
+    schema: SharedSchema,
+    options: SyncOptions,
+    client: reqwest::Client,
+    legacy_db_path: Option<PathBuf>,
+}
+
+/// Builder of the sync plugin.
+pub struct Builder {
+    plugin: SyncPlugin,
+}
+
+impl Builder {
+    pub fn new(schema: impl SyncSchema + Send + Sync + 'static) -> Self {
+        Self {
+            plugin: SyncPlugin {
+                schema: SharedSchema(Arc::new(schema)),
+                options: SyncOptions::default(),
+                client: reqwest::Client::new(),
+                legacy_db_path: None,
+            },
+        }
+    }
+
+    /// Options used by `manual_sync`.
+    pub fn options(mut self, options: SyncOptions) -> Self {
+        self.plugin.options = options;
+        self
+    }
+
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.plugin.client = client;
+        self
+    }
+
+    /// Database file of a previous app version, offered for import by `migrate_from_legacy`.
+    pub fn legacy_db_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.plugin.legacy_db_path = Some(path.into());
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        let plugin = self.plugin;
+        PluginBuilder::new(PLUGIN_NAME)
+            .invoke_handler(tauri::generate_handler![
+                configure_sync,
+                get_sync_config,
+                manual_sync,
+                is_cloud_sync_enabled,
+                has_legacy_db,
+                migrate_from_legacy,
+                list_profiles,
+                add_profile,
+                remove_profile,
+                activate_profile,
+            ])
+            .setup(move |app, _api| {
+                if let Some(db) = app.try_state::<DbState>() {
+                    if let Err(e) = profiles::migrate_config(&db) {
+                        eprintln!("Warning: failed to migrate sync config: {}", e);
+                    }
+                }
+                app.manage(plugin);
+                Ok(())
+            })
+            .build()
+    }
+}
+
+/// Save the URL and token of the active profile. An empty token keeps the stored one.
+#[tauri::command]
+async fn configure_sync(db: State<'_, DbState>, url: String, token: String) -> Result<(), SyncDbError> {
+    backend::configure_sync(&db, url, token).await
+}
+
+/// The active connection, with whether a token is set instead of the token.
+#[tauri::command]
+fn get_sync_config(db: State<'_, DbState>) -> Option<SyncConfigInfo> {
+    backend::get_sync_config(&db)
+}
+
+#[tauri::command]
+fn is_cloud_sync_enabled(db: State<'_, DbState>) -> bool {
+    db.is_cloud_sync_enabled()
+}
+
+/// Sync now. Resolves with the report also when tables failed, so the frontend gets their
+/// counts and errors; fails only when the run could not start, e.g. on an auth error.
+#[tauri::command]
+async fn manual_sync<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, DbState>,
+    plugin: State<'_, SyncPlugin>,
+) -> Result<SyncReport, SyncDbError> {
+    let config = db.sync_config()
+        .ok_or_else(|| SyncDbError::Config { message: "Cloud sync is not configured".to_string() })?;
+
+    let mut options = plugin.options.clone();
+    options.profile = config.profile;
+    let app_progress = options.progress.take();
+    options.progress = Some(ProgressCallback::new(move |progress| {
+        if let Some(callback) = &app_progress {
+            callback.emit(progress);
+        }
+        if let Err(e) = app.emit(PROGRESS_EVENT, progress) {
+            eprintln!("Warning: failed to emit sync progress: {}", e);
+        }
+    }));
+
+    sync_all_with_options(&plugin.client, &db, &plugin.schema, &config.url, &config.token, &options).await
+}
+
+#[tauri::command]
+fn has_legacy_db(plugin: State<'_, SyncPlugin>) -> bool {
+    plugin.legacy_db_path.as_ref().is_some_and(|path| path.exists())
+}
+
+#[tauri::command]
+async fn migrate_from_legacy(db: State<'_, DbState>, plugin: State<'_, SyncPlugin>) -> Result<String, SyncDbError> {
+    let path = plugin.legacy_db_path.as_ref()
+        .ok_or_else(|| SyncDbError::Config { message: "No legacy database configured".to_string() })?;
+    let copied = backend::import_legacy_db(&db, path, &plugin.schema.tables()).await?;
+    Ok(format!("Imported {} rows from the legacy database", copied))
+}
+
+#[tauri::command]
+fn list_profiles(db: State<'_, DbState>) -> Result<SyncProfiles, SyncDbError> {
+    profiles::list_profiles(&db)
+}
+
+#[tauri::command]
+async fn add_profile(db: State<'_, DbState>, name: String, url: String, token: String) -> Result<(), SyncDbError> {
+    profiles::add_profile(&db, &name, url, token).await
+}
+
+#[tauri::command]
+async fn remove_profile(db: State<'_, DbState>, name: String) -> Result<(), SyncDbError> {
+    profiles::remove_profile(&db, &name).await
+}
+
+#[tauri::command]
+fn activate_profile(db: State<'_, DbState>, name: String) -> Result<(), SyncDbError> {
+    profiles::activate_profile(&db, &name)
+}
@@ -0,0 +1,116 @@
+//! Progress reporting and cancellation
+//!
+//! A running sync reports [`SyncProgress`] events through a [`ProgressCallback`] and can be
+//! stopped with a [`CancelToken`]. Cancellation takes effect between batches: pages already
+//! pulled are kept and the pull resumes from there next time, while an interrupted table's
+//! watermarks are left untouched.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Stage of a sync run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+    SchemaCheck,
+    Pull,
+    Push,
+    Finished,
+}
+
+/// A progress event, serializable so a Tauri app can forward it as-is with `emit`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    pub phase: SyncPhase,
+    /// Table being synced; `None` for the schema check and the final event.
+    pub table: Option<String>,
+    /// Position of the table in the sync order, starting at 0.
+    pub table_index: usize,
+    pub table_count: usize,
+    /// Rows pulled or pushed so far in this phase of the table.
+    pub rows: usize,
+}
+
+/// Receiver of progress events.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&SyncProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(f: impl Fn(&SyncProgress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// A callback forwarding events to a channel, for consumers on another task.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<SyncProgress>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self::new(move |p| { let _ = tx.send(p.clone()); }), rx)
+    }
+
+    pub(crate) fn emit(&self, progress: &SyncProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Requests a running sync to stop. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    /// Token whose cancellation also cancels this one.
+    parent: Option<Box<CancelToken>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new token cancelled along with this one, but which can be cancelled on its own.
+    pub fn child(&self) -> Self {
+        Self { flag: Arc::default(), parent: Some(Box::new(self.clone())) }
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst) || self.parent.as_ref().is_some_and(|p| p.is_cancelled())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_and_cancel() {
+        let (callback, mut rx) = ProgressCallback::channel();
+        let event = SyncProgress { phase: SyncPhase::Pull, table: Some("notes".into()), table_index: 0, table_count: 2, rows: 10 };
+        callback.emit(&event);
+        assert_eq!(rx.try_recv().unwrap(), event);
+        assert_eq!(serde_json::to_value(&event).unwrap()["phase"], "pull");
+
+        let token = CancelToken::new();
+        let shared = token.clone();
+        assert!(!token.is_cancelled());
+        shared.cancel();
+        assert!(token.is_cancelled());
+
+        let parent = CancelToken::new();
+        let (first, second) = (parent.child(), parent.child());
+        first.cancel();
+        assert!(!parent.is_cancelled() && !second.is_cancelled());
+        parent.cancel();
+        assert!(second.is_cancelled());
+    }
+}
@@ -0,0 +1,503 @@
+//! Remote database client
+//!
+//! [`RemoteDb`] talks to Turso over Hrana, using the `v2/pipeline` endpoint. Each pipeline
+//! request runs on a server-side stream identified by a baton; keeping the baton between
+//! requests keeps the stream, and an explicit `BEGIN` on it, open. That is what makes
+//! multi-request transactions possible.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri_plugin_http::reqwest;
+
+use crate::value::{Row, SyncValue};
+
+/// A SQL statement sent to the remote with positional (`?`) parameters.
+#[derive(Debug, Clone)]
+pub struct RemoteStatement {
+    pub sql: String,
+    pub params: Vec<SyncValue>,
+}
+
+impl RemoteStatement {
+    pub fn new(sql: impl Into<String>, params: Vec<SyncValue>) -> Self {
+        Self { sql: sql.into(), params }
+    }
+}
+
+impl From<String> for RemoteStatement {
+    fn from(sql: String) -> Self {
+        Self::new(sql, Vec::new())
+    }
+}
+
+impl From<&str> for RemoteStatement {
+    fn from(sql: &str) -> Self {
+        Self::new(sql, Vec::new())
+    }
+}
+
+/// Error from a remote request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteError {
+    /// The request could not be sent or its response not read.
+    Network(String),
+    /// The server answered with a non-success HTTP status, e.g. 401 for a bad token.
+    Http { status: u16, body: String, retry_after: Option<Duration> },
+    /// The response was not a valid Hrana response.
+    Protocol(String),
+    /// The database rejected a statement. `index` is its position among the statements of the
+    /// call, `None` for the `BEGIN`/`COMMIT` of a transaction.
+    Statement { message: String, sql: String, index: Option<usize> },
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::Network(e) => write!(f, "HTTP request failed: {}", e),
+            RemoteError::Http { status, body, .. } => write!(f, "Remote request failed with HTTP {}: {}", status, body),
+            RemoteError::Protocol(e) => write!(f, "Invalid remote response: {}", e),
+            RemoteError::Statement { message, sql, .. } => write!(f, "{} (in statement: {})", message, sql),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// Broad cause of a [`RemoteError`], deciding whether it is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteErrorKind {
+    /// Connection, DNS or timeout failure.
+    Network,
+    /// Missing, invalid or expired token (HTTP 401/403).
+    Auth,
+    /// Too many requests (HTTP 429).
+    RateLimited,
+    /// Server-side failure (HTTP 5xx).
+    Server,
+    /// A statement was rejected by the database.
+    Sql,
+    /// Malformed request or response, including other 4xx statuses.
+    Protocol,
+}
+
+impl RemoteError {
+    pub fn kind(&self) -> RemoteErrorKind {
+        match self {
+            RemoteError::Network(_) => RemoteErrorKind::Network,
+            RemoteError::Http { status: 401 | 403, .. } => RemoteErrorKind::Auth,
+            RemoteError::Http { status: 429, .. } => RemoteErrorKind::RateLimited,
+            RemoteError::Http { status: 500.., .. } => RemoteErrorKind::Server,
+            RemoteError::Http { .. } | RemoteError::Protocol(_) => RemoteErrorKind::Protocol,
+            RemoteError::Statement { .. } => RemoteErrorKind::Sql,
+        }
+    }
+
+    /// Whether the failure is transient, so the same request may succeed later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), RemoteErrorKind::Network | RemoteErrorKind::RateLimited | RemoteErrorKind::Server)
+    }
+
+    /// Shift the index of a failed statement, for statements sent in several calls.
+    pub(crate) fn offset_statement(self, offset: usize) -> Self {
+        match self {
+            RemoteError::Statement { message, sql, index } => RemoteError::Statement { message, sql, index: index.map(|i| i + offset) },
+            e => e,
+        }
+    }
+
+    /// Delay requested by the server through `Retry-After`.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RemoteError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, given either in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+impl From<RemoteError> for String {
+    fn from(e: RemoteError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Result of one executed statement.
+#[derive(Debug, Clone, Default)]
+pub struct StmtResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+    pub affected_row_count: u64,
+    pub last_insert_rowid: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipelineResponse {
+    baton: Option<String>,
+    base_url: Option<String>,
+    results: Vec<StreamResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamResult {
+    Ok { response: StreamResponse },
+    Error { error: HranaError },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamResponse {
+    Execute { result: ExecuteResult },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct HranaError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecuteResult {
+    cols: Vec<HranaColumn>,
+    rows: Vec<Vec<Value>>,
+    #[serde(default)]
+    affected_row_count: u64,
+    last_insert_rowid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HranaColumn {
+    name: Option<String>,
+}
+
+impl From<ExecuteResult> for StmtResult {
+    fn from(result: ExecuteResult) -> Self {
+        StmtResult {
+            columns: result.cols.into_iter().map(|c| c.name.unwrap_or_default()).collect(),
+            rows: result.rows.iter().map(|r| r.iter().map(SyncValue::from_hrana).collect()).collect(),
+            affected_row_count: result.affected_row_count,
+            last_insert_rowid: result.last_insert_rowid.and_then(|id| id.parse().ok()),
+        }
+    }
+}
+
+fn execute_request(stmt: &RemoteStatement) -> Value {
+    json!({
+        "type": "execute",
+        "stmt": {
+            "sql": stmt.sql,
+            "args": stmt.params.iter().map(SyncValue::to_hrana).collect::<Vec<_>>(),
+        },
+    })
+}
+
+/// Turn `libsql://` database URLs into the HTTPS base URL.
+fn http_base_url(url: &str) -> String {
+    url.replace("libsql://", "https://").trim_end_matches('/').to_string()
+}
+
+fn statement_error(error: HranaError, sql: &str, index: Option<usize>) -> RemoteError {
+    RemoteError::Statement { message: error.message, sql: sql.to_string(), index }
+}
+
+/// Connection to a remote database. Cheap to clone; clones share the HTTP client.
+#[derive(Clone)]
+pub struct RemoteDb {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl fmt::Debug for RemoteDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteDb").field("base_url", &self.base_url).finish_non_exhaustive()
+    }
+}
+
+impl RemoteDb {
+    /// `url` may use the `libsql://` scheme, which is mapped to HTTPS.
+    pub fn new(client: reqwest::Client, url: &str, token: &str) -> Self {
+        Self { client, base_url: http_base_url(url), token: token.to_string() }
+    }
+
+    /// HTTP base URL requests are sent to.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn pipeline(&self, base_url: &str, baton: Option<&str>, requests: Vec<Value>) -> Result<PipelineResponse, RemoteError> {
+        let response = self.client
+            .post(format!("{}/v2/pipeline", base_url))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Content-Type", "application/json")
+            .body(json!({ "baton": baton, "requests": requests }).to_string())
+            .send()
+            .await
+            .map_err(|e| RemoteError::Network(e.to_string()))?;
+
+        let status = response.status();
+        let retry_after = response.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let text = response.text().await.map_err(|e| RemoteError::Network(e.to_string()))?;
+        if !status.is_success() {
+            return Err(RemoteError::Http { status: status.as_u16(), body: text, retry_after });
+        }
+        serde_json::from_str(&text).map_err(|e| RemoteError::Protocol(format!("{} (Body: {})", e, text)))
+    }
+
+    /// Run statements on a fresh stream, then close it. Statements run independently:
+    /// a failing statement does not stop the following ones.
+    pub async fn batch(&self, statements: Vec<RemoteStatement>) -> Result<Vec<Result<StmtResult, RemoteError>>, RemoteError> {
+        let count = statements.len();
+        let mut requests: Vec<Value> = statements.iter().map(execute_request).collect();
+        requests.push(json!({ "type": "close" }));
+
+        let response = self.pipeline(&self.base_url, None, requests).await?;
+        if response.results.len() < count {
+            return Err(RemoteError::Protocol(format!("expected {} results, got {}", count, response.results.len())));
+        }
+        Ok(response.results.into_iter().zip(&statements).enumerate().map(|(i, (result, stmt))| match result {
+            StreamResult::Ok { response: StreamResponse::Execute { result } } => Ok(result.into()),
+            StreamResult::Ok { .. } => Err(RemoteError::Protocol("unexpected response to execute request".to_string())),
+            StreamResult::Error { error } => Err(statement_error(error, &stmt.sql, Some(i))),
+        }).collect())
+    }
+
+    /// Execute a single statement.
+    pub async fn execute(&self, stmt: RemoteStatement) -> Result<StmtResult, RemoteError> {
+        self.batch(vec![stmt]).await?
+            .pop()
+            .unwrap_or_else(|| Err(RemoteError::Protocol("missing result for statement".to_string())))
+    }
+
+    /// Rows returned by a single query.
+    pub async fn query(&self, stmt: RemoteStatement) -> Result<Vec<Row>, RemoteError> {
+        self.execute(stmt).await.map(|r| r.rows)
+    }
+
+    /// Open a transaction. `BEGIN` is sent, and checked, before the first statements.
+    pub fn transaction(&self) -> RemoteTransaction<'_> {
+        RemoteTransaction { db: self, base_url: self.base_url.clone(), baton: None, started: false }
+    }
+
+    /// Execute statements atomically: all of them are committed, or none.
+    pub async fn execute_transaction(&self, statements: Vec<RemoteStatement>) -> Result<Vec<StmtResult>, RemoteError> {
+        let mut tx = self.transaction();
+        let results = tx.execute(statements).await?;
+        tx.commit().await?;
+        Ok(results)
+    }
+}
+
+/// A transaction spanning several pipeline requests.
+///
+/// A transaction dropped without `commit` is rolled back by the server when its stream expires.
+pub struct RemoteTransaction<'a> {
+    db: &'a RemoteDb,
+    base_url: String,
+    baton: Option<String>,
+    started: bool,
+}
+
+impl RemoteTransaction<'_> {
+    async fn send(&mut self, requests: Vec<Value>) -> Result<Vec<StreamResult>, RemoteError> {
+        let response = self.db.pipeline(&self.base_url, self.baton.as_deref(), requests).await?;
+        self.baton = response.baton;
+        if let Some(base_url) = response.base_url {
+            self.base_url = base_url;
+        }
+        Ok(response.results)
+    }
+
+    /// Execute statements inside the transaction. On the first failing statement the
+    /// transaction is rolled back and the error returned.
+    pub async fn execute(&mut self, statements: Vec<RemoteStatement>) -> Result<Vec<StmtResult>, RemoteError> {
+        if statements.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // BEGIN goes alone: statements pipelined after a failed BEGIN would still run, in
+        // autocommit mode
+        if !self.started {
+            let begin = match self.send(vec![execute_request(&"BEGIN".into())]).await {
+                Ok(results) => results.into_iter().next(),
+                Err(e) => {
+                    self.abort().await;
+                    return Err(e);
+                }
+            };
+            match begin {
+                Some(StreamResult::Ok { .. }) => self.started = true,
+                Some(StreamResult::Error { error }) => {
+                    self.abort().await;
+                    return Err(statement_error(error, "BEGIN", None));
+                }
+                None => {
+                    self.abort().await;
+                    return Err(RemoteError::Protocol("missing result for BEGIN".to_string()));
+                }
+            }
+        }
+
+        let results = match self.send(statements.iter().map(execute_request).collect()).await {
+            Ok(results) => results,
+            Err(e) => {
+                self.abort().await;
+                return Err(e);
+            }
+        };
+
+        let mut out = Vec::with_capacity(results.len());
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                StreamResult::Ok { response: StreamResponse::Execute { result } } => out.push(result.into()),
+                StreamResult::Ok { .. } => out.push(StmtResult::default()),
+                StreamResult::Error { error } => {
+                    self.abort().await;
+                    return Err(statement_error(error, &statements[i].sql, Some(i)));
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    pub async fn commit(mut self) -> Result<(), RemoteError> {
+        if !self.started {
+            return Ok(());
+        }
+        let results = self.send(vec![execute_request(&"COMMIT".into()), json!({ "type": "close" })]).await?;
+        match results.into_iter().next() {
+            Some(StreamResult::Ok { .. }) => Ok(()),
+            Some(StreamResult::Error { error }) => Err(statement_error(error, "COMMIT", None)),
+            None => Err(RemoteError::Protocol("missing result for COMMIT".to_string())),
+        }
+    }
+
+    pub async fn rollback(mut self) -> Result<(), RemoteError> {
+        if !self.started {
+            return Ok(());
+        }
+        self.send(vec![execute_request(&"ROLLBACK".into()), json!({ "type": "close" })]).await?;
+        Ok(())
+    }
+
+    /// Best-effort rollback after a failure; the server discards the stream on its own otherwise.
+    async fn abort(&mut self) {
+        if self.baton.is_some() {
+            if let Err(e) = self.send(vec![execute_request(&"ROLLBACK".into()), json!({ "type": "close" })]).await {
+                eprintln!("Warning: remote rollback failed: {}", e);
+            }
+        }
+        self.baton = None;
+        self.started = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute_request() {
+        let stmt = RemoteStatement::new("INSERT INTO t VALUES (?, ?)", vec![SyncValue::Integer(1), SyncValue::Null]);
+        assert_eq!(execute_request(&stmt), json!({
+            "type": "execute",
+            "stmt": {
+                "sql": "INSERT INTO t VALUES (?, ?)",
+                "args": [{ "type": "integer", "value": "1" }, { "type": "null" }],
+            },
+        }));
+    }
+
+    #[test]
+    fn test_parse_pipeline_response() {
+        let body = r#"{
+            "baton": "b1",
+            "base_url": null,
+            "results": [
+                { "type": "ok", "response": { "type": "execute", "result": {
+                    "cols": [{ "name": "id", "decltype": "INTEGER" }, { "name": "title" }],
+                    "rows": [[{ "type": "integer", "value": "7" }, { "type": "text", "value": "hi" }]],
+                    "affected_row_count": 0,
+                    "last_insert_rowid": null
+                } } },
+                { "type": "error", "error": { "message": "no such table: x", "code": "SQLITE_ERROR" } },
+                { "type": "ok", "response": { "type": "close" } }
+            ]
+        }"#;
+        let response: PipelineResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.baton.as_deref(), Some("b1"));
+
+        let mut results = response.results.into_iter();
+        let Some(StreamResult::Ok { response: StreamResponse::Execute { result } }) = results.next() else {
+            panic!("expected execute result");
+        };
+        let result = StmtResult::from(result);
+        assert_eq!(result.columns, vec!["id", "title"]);
+        assert_eq!(result.rows, vec![vec![SyncValue::Integer(7), "hi".into()]]);
+        assert!(matches!(results.next(), Some(StreamResult::Error { error }) if error.message == "no such table: x"));
+        assert!(matches!(results.next(), Some(StreamResult::Ok { response: StreamResponse::Other })));
+    }
+
+    #[test]
+    fn test_http_base_url() {
+        assert_eq!(http_base_url("libsql://db-org.turso.io/"), "https://db-org.turso.io");
+        assert_eq!(http_base_url("http://127.0.0.1:8080"), "http://127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = statement_error(HranaError { message: "no such table: x".into() }, "SELECT * FROM x", Some(0));
+        assert_eq!(String::from(error), "no such table: x (in statement: SELECT * FROM x)");
+        let error = RemoteError::Http { status: 401, body: "Unauthorized".into(), retry_after: None };
+        assert_eq!(error.to_string(), "Remote request failed with HTTP 401: Unauthorized");
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let http = |status| RemoteError::Http { status, body: String::new(), retry_after: None };
+        assert_eq!(http(401).kind(), RemoteErrorKind::Auth);
+        assert_eq!(http(429).kind(), RemoteErrorKind::RateLimited);
+        assert_eq!(http(502).kind(), RemoteErrorKind::Server);
+        assert_eq!(http(400).kind(), RemoteErrorKind::Protocol);
+        assert!(!http(403).is_retryable());
+        assert!(RemoteError::Network("dns error".into()).is_retryable());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_begin_writes_nothing() {
+        let remote = crate::test_util::MockTurso::start().await.unwrap();
+        remote.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY)").unwrap();
+        let db = RemoteDb::new(reqwest::Client::new(), &remote.url(), remote.token());
+
+        remote.fail_statement("BEGIN");
+        let err = db.execute_transaction(vec!["INSERT INTO t VALUES (1)".into()]).await.unwrap_err();
+        assert!(matches!(&err, RemoteError::Statement { sql, index: None, .. } if sql == "BEGIN"), "{}", err);
+        assert!(remote.query("SELECT * FROM t").unwrap().is_empty());
+
+        db.execute_transaction(vec!["INSERT INTO t VALUES (1)".into()]).await.unwrap();
+        assert_eq!(remote.query("SELECT id FROM t").unwrap(), vec![vec![SyncValue::Integer(1)]]);
+    }
+}
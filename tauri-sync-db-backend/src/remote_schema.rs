@@ -0,0 +1,297 @@
+//! Remote schema migration
+//!
+//! Compares the local definition of the synced tables with the remote database and
+//! plans the statements that bring the remote up to date: missing tables, columns and
+//! indexes are created, while type or primary key differences are reported as errors.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use rusqlite::Connection;
+
+use crate::backend::metadata::{DELETE_LOG_DDL, DELETE_LOG_HLC_DDL};
+use crate::error::SyncDbError;
+use crate::value::{Row, SyncValue};
+
+/// `_sync_meta` key of the hash of the last schema verified against the remote.
+pub(crate) const SCHEMA_HASH_KEY: &str = "remote_schema_hash";
+
+/// Columns of every remote table, in a single request.
+pub(crate) const REMOTE_COLUMNS_SQL: &str = "SELECT m.name, p.name, p.type, p.pk
+    FROM sqlite_master m JOIN pragma_table_info(m.name) p
+    WHERE m.type = 'table'
+    ORDER BY m.name, p.cid";
+
+/// Named indexes of the remote database.
+pub(crate) const REMOTE_INDEXES_SQL: &str = "SELECT name FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL";
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub(crate) struct ColumnDef {
+    pub name: String,
+    pub col_type: String,
+    pub notnull: bool,
+    pub default: Option<String>,
+    /// Position in the primary key, 0 when not part of it.
+    pub pk: i64,
+}
+
+/// A synced table as defined locally.
+#[derive(Debug, Clone, Hash)]
+pub(crate) struct LocalTable {
+    pub name: String,
+    pub create_sql: String,
+    pub columns: Vec<ColumnDef>,
+    /// Name and `CREATE INDEX` statement of each explicitly created index.
+    pub indexes: Vec<(String, String)>,
+}
+
+/// Remote column as returned by [`REMOTE_COLUMNS_SQL`].
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteColumn {
+    pub name: String,
+    pub col_type: String,
+    pub pk: i64,
+}
+
+pub(crate) fn read_local_tables(conn: &Connection, tables: &[&str]) -> Result<Vec<LocalTable>, SyncDbError> {
+    let mut result = Vec::new();
+    for &table in tables {
+        let create_sql: Option<String> = conn
+            .query_row("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |r| r.get(0))
+            .ok();
+        let Some(create_sql) = create_sql else {
+            eprintln!("Warning: table {} does not exist locally, not creating it on the remote", table);
+            continue;
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1) ORDER BY cid"
+        )?;
+        let columns = stmt.query_map([table], |r| Ok(ColumnDef {
+            name: r.get(0)?,
+            col_type: r.get(1)?,
+            notnull: r.get(2)?,
+            default: r.get(3)?,
+            pk: r.get(4)?,
+        }))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT name, sql FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1 AND sql IS NOT NULL ORDER BY name"
+        )?;
+        let indexes = stmt.query_map([table], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        result.push(LocalTable { name: table.to_string(), create_sql, columns, indexes });
+    }
+    Ok(result)
+}
+
+/// Fingerprint of the local schema and the remote it was verified against.
+///
+/// Only compared with the value cached by the same build, so the std hasher is stable enough;
+/// a different value merely triggers another verification.
+pub(crate) fn schema_hash(url: &str, tables: &[LocalTable], delete_log: bool) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    url.hash(&mut hasher);
+    tables.hash(&mut hasher);
+    delete_log.then_some((DELETE_LOG_DDL, DELETE_LOG_HLC_DDL)).hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Group rows of [`REMOTE_COLUMNS_SQL`] by table.
+pub(crate) fn remote_columns(rows: Vec<Row>) -> HashMap<String, Vec<RemoteColumn>> {
+    let mut tables: HashMap<String, Vec<RemoteColumn>> = HashMap::new();
+    for row in rows {
+        let [SyncValue::Text(table), SyncValue::Text(name), col_type, pk] = &row[..] else {
+            continue;
+        };
+        tables.entry(table.clone()).or_default().push(RemoteColumn {
+            name: name.clone(),
+            col_type: match col_type {
+                SyncValue::Text(t) => t.clone(),
+                _ => String::new(),
+            },
+            pk: match pk {
+                SyncValue::Integer(i) => *i,
+                _ => 0,
+            },
+        });
+    }
+    tables
+}
+
+/// SQLite type affinity of a declared column type.
+fn type_affinity(decl: &str) -> &'static str {
+    let decl = decl.to_uppercase();
+    if decl.contains("INT") {
+        "INTEGER"
+    } else if decl.contains("CHAR") || decl.contains("CLOB") || decl.contains("TEXT") {
+        "TEXT"
+    } else if decl.is_empty() || decl.contains("BLOB") {
+        "BLOB"
+    } else if decl.contains("REAL") || decl.contains("FLOA") || decl.contains("DOUB") {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
+
+/// Default for a column added with `ALTER TABLE`, which only accepts constant defaults.
+fn added_column_default(column: &ColumnDef) -> Option<String> {
+    if column.name == "deleted_at" {
+        // Must stay NULL for live rows, any other value marks a tombstone
+        return None;
+    }
+    let constant = column.default.as_deref()
+        .filter(|d| !d.starts_with('(') && !d.to_uppercase().starts_with("CURRENT_"));
+    if let Some(default) = constant {
+        return Some(default.to_string());
+    }
+    match column.name.as_str() {
+        "updated_at" | "created_at" if type_affinity(&column.col_type) == "INTEGER" => Some("0".to_string()),
+        "updated_at" | "created_at" => Some("'1970-01-01T00:00:00'".to_string()),
+        _ => None,
+    }
+}
+
+/// Statements bringing the remote schema in line with `local`, or every incompatibility found.
+pub(crate) fn plan_migration(
+    local: &[LocalTable],
+    remote: &HashMap<String, Vec<RemoteColumn>>,
+    remote_indexes: &HashSet<String>,
+) -> Result<Vec<String>, Vec<String>> {
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+
+    for table in local {
+        let Some(remote_cols) = remote.get(&table.name) else {
+            statements.push(table.create_sql.clone());
+            statements.extend(table.indexes.iter().map(|(_, sql)| sql.clone()));
+            continue;
+        };
+
+        let mut local_pk: Vec<&ColumnDef> = table.columns.iter().filter(|c| c.pk > 0).collect();
+        local_pk.sort_by_key(|c| c.pk);
+        let mut remote_pk: Vec<&RemoteColumn> = remote_cols.iter().filter(|c| c.pk > 0).collect();
+        remote_pk.sort_by_key(|c| c.pk);
+        let local_pk: Vec<&str> = local_pk.iter().map(|c| c.name.as_str()).collect();
+        let remote_pk: Vec<&str> = remote_pk.iter().map(|c| c.name.as_str()).collect();
+        if local_pk != remote_pk {
+            errors.push(format!(
+                "{}: primary key ({}) differs from remote ({})",
+                table.name, local_pk.join(", "), remote_pk.join(", ")
+            ));
+        }
+
+        for column in &table.columns {
+            match remote_cols.iter().find(|c| c.name.eq_ignore_ascii_case(&column.name)) {
+                Some(remote_col) => {
+                    if type_affinity(&column.col_type) != type_affinity(&remote_col.col_type) {
+                        errors.push(format!(
+                            "{}.{}: local type {} is incompatible with remote type {}",
+                            table.name, column.name, column.col_type, remote_col.col_type
+                        ));
+                    }
+                }
+                None => {
+                    let default = added_column_default(column);
+                    if column.notnull && default.is_none() {
+                        errors.push(format!(
+                            "{}.{}: NOT NULL column without a constant default can't be added to the remote",
+                            table.name, column.name
+                        ));
+                        continue;
+                    }
+                    let mut sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table.name, column.name, column.col_type);
+                    if column.notnull {
+                        sql.push_str(" NOT NULL");
+                    }
+                    if let Some(default) = default {
+                        sql.push_str(&format!(" DEFAULT {}", default));
+                    }
+                    statements.push(sql);
+                }
+            }
+        }
+
+        for (name, sql) in &table.indexes {
+            if !remote_indexes.contains(name) {
+                statements.push(sql.clone());
+            }
+        }
+    }
+
+    if errors.is_empty() { Ok(statements) } else { Err(errors) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(conn: &Connection, table: &str) -> LocalTable {
+        read_local_tables(conn, &[table]).unwrap().pop().unwrap()
+    }
+
+    fn remote(columns: &[(&str, &str, i64)]) -> Vec<RemoteColumn> {
+        columns.iter()
+            .map(|&(name, col_type, pk)| RemoteColumn { name: name.into(), col_type: col_type.into(), pk })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_adds_missing_columns_and_indexes() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                id INTEGER PRIMARY KEY,
+                title TEXT NOT NULL DEFAULT '',
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                deleted_at TEXT
+            );
+            CREATE INDEX notes_updated ON notes (updated_at);"
+        ).unwrap();
+        let table = local(&conn, "notes");
+
+        let remote_tables = HashMap::from([("notes".to_string(), remote(&[("id", "INTEGER", 1), ("title", "TEXT", 0)]))]);
+        let statements = plan_migration(std::slice::from_ref(&table), &remote_tables, &HashSet::new()).unwrap();
+        assert_eq!(statements, vec![
+            "ALTER TABLE notes ADD COLUMN updated_at TEXT DEFAULT '1970-01-01T00:00:00'",
+            "ALTER TABLE notes ADD COLUMN deleted_at TEXT",
+            "CREATE INDEX notes_updated ON notes (updated_at)",
+        ]);
+
+        // A missing table is created from the local definition
+        let statements = plan_migration(std::slice::from_ref(&table), &HashMap::new(), &HashSet::new()).unwrap();
+        assert_eq!(statements, vec![table.create_sql.clone(), table.indexes[0].1.clone()]);
+    }
+
+    #[test]
+    fn test_plan_reports_incompatibilities() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (id TEXT PRIMARY KEY, qty INTEGER, sku TEXT NOT NULL)"
+        ).unwrap();
+        let table = local(&conn, "items");
+
+        let remote_tables = HashMap::from([("items".to_string(), remote(&[("id", "INTEGER", 0), ("qty", "BIGINT", 0)]))]);
+        let errors = plan_migration(&[table], &remote_tables, &HashSet::new()).unwrap_err();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].contains("primary key"));
+        assert!(errors[1].contains("items.id"));
+        assert!(errors[2].contains("items.sku"));
+    }
+
+    #[test]
+    fn test_schema_hash_tracks_changes() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE t (id INTEGER PRIMARY KEY)", []).unwrap();
+        let before = read_local_tables(&conn, &["t"]).unwrap();
+        conn.execute("ALTER TABLE t ADD COLUMN name TEXT", []).unwrap();
+        let after = read_local_tables(&conn, &["t"]).unwrap();
+
+        assert_eq!(schema_hash("libsql://a", &before, false), schema_hash("libsql://a", &before, false));
+        assert_ne!(schema_hash("libsql://a", &before, false), schema_hash("libsql://a", &after, false));
+        assert_ne!(schema_hash("libsql://a", &before, false), schema_hash("libsql://b", &before, false));
+    }
+}
@@ -0,0 +1,175 @@
+//! Sync results
+//!
+//! `sync_all` returns a `SyncReport` describing what happened to each table,
+//! so apps can surface real results in the UI and in logs.
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of syncing a single table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableReport {
+    pub table: String,
+    /// Rows sent to the remote.
+    pub pushed: usize,
+    /// Remote rows applied locally.
+    pub pulled: usize,
+    /// Remote rows ignored because the local version was newer.
+    pub skipped_collisions: usize,
+    /// Rows changed on both sides since the last sync.
+    pub conflicts: usize,
+    /// Conflicts the resolver left for the app, recorded in `sync_conflicts`.
+    pub unresolved_conflicts: usize,
+    /// Hard deletes sent to the remote.
+    pub deletes_pushed: usize,
+    /// Remote deletes replayed locally.
+    pub deletes_pulled: usize,
+    pub duration_ms: u64,
+    /// Set when the table failed; counts reflect the work done before the failure.
+    pub error: Option<String>,
+}
+
+impl TableReport {
+    pub fn new(table: &str) -> Self {
+        Self { table: table.to_string(), ..Default::default() }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// A row left referencing a missing parent, as reported by `PRAGMA foreign_key_check`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    /// `None` for `WITHOUT ROWID` tables.
+    pub rowid: Option<i64>,
+    /// Referenced table.
+    pub parent: String,
+    /// Index of the foreign key in `PRAGMA foreign_key_list`.
+    pub fk_index: i64,
+}
+
+/// Outcome of a full `sync_all` run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// One entry per table that was attempted, in sync order.
+    pub tables: Vec<TableReport>,
+    /// Tables that were not attempted: the rest of a cancelled run, or in strict mode the
+    /// tables depending on a failed one.
+    pub skipped_tables: Vec<String>,
+    /// Dangling references found in the synced tables after pulling.
+    pub fk_violations: Vec<ForeignKeyViolation>,
+    /// Whether the run was cancelled; the interrupted and remaining tables are left for the next run.
+    pub cancelled: bool,
+    pub duration_ms: u64,
+}
+
+impl SyncReport {
+    /// True when every table was attempted and none failed.
+    pub fn is_success(&self) -> bool {
+        self.skipped_tables.is_empty() && self.tables.iter().all(TableReport::is_success)
+    }
+
+    /// Table name and error of every failed table.
+    pub fn errors(&self) -> Vec<(&str, &str)> {
+        self.tables.iter()
+            .filter_map(|t| t.error.as_deref().map(|e| (t.table.as_str(), e)))
+            .collect()
+    }
+
+    pub fn total_pushed(&self) -> usize {
+        self.tables.iter().map(|t| t.pushed + t.deletes_pushed).sum()
+    }
+
+    pub fn total_pulled(&self) -> usize {
+        self.tables.iter().map(|t| t.pulled + t.deletes_pulled).sum()
+    }
+
+    /// One-line summary for logs and status bars.
+    pub fn summary(&self) -> String {
+        let mut summary = self.counts_summary();
+        if !self.fk_violations.is_empty() {
+            summary.push_str(&format!(", {} foreign key violations", self.fk_violations.len()));
+        }
+        if self.cancelled {
+            summary.push_str(" (cancelled)");
+        }
+        summary
+    }
+
+    fn counts_summary(&self) -> String {
+        let errors = self.errors();
+        if errors.is_empty() && self.skipped_tables.is_empty() {
+            format!(
+                "Synced {} tables in {} ms: {} pushed, {} pulled",
+                self.tables.len(), self.duration_ms, self.total_pushed(), self.total_pulled()
+            )
+        } else {
+            format!(
+                "Synced {} tables in {} ms with {} errors ({} skipped): {} pushed, {} pulled",
+                self.tables.len(), self.duration_ms, errors.len(), self.skipped_tables.len(),
+                self.total_pushed(), self.total_pulled()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str, pushed: usize, pulled: usize, error: Option<&str>) -> TableReport {
+        TableReport {
+            pushed,
+            pulled,
+            deletes_pushed: 1,
+            deletes_pulled: 2,
+            error: error.map(str::to_string),
+            ..TableReport::new(name)
+        }
+    }
+
+    #[test]
+    fn test_totals_include_deletes() {
+        let report = SyncReport {
+            tables: vec![table("notes", 3, 4, None), table("tags", 0, 5, None)],
+            ..Default::default()
+        };
+        assert_eq!((report.total_pushed(), report.total_pulled()), (5, 13));
+    }
+
+    #[test]
+    fn test_success_and_summary() {
+        let mut report = SyncReport {
+            tables: vec![table("notes", 3, 4, None)],
+            duration_ms: 12,
+            ..Default::default()
+        };
+        assert!(report.is_success());
+        assert!(report.errors().is_empty());
+        assert_eq!(report.summary(), "Synced 1 tables in 12 ms: 4 pushed, 6 pulled");
+
+        report.tables.push(table("tags", 0, 0, Some("rejected")));
+        report.skipped_tables.push("note_tags".to_string());
+        assert!(!report.is_success());
+        assert_eq!(report.errors(), vec![("tags", "rejected")]);
+        assert_eq!(report.summary(), "Synced 2 tables in 12 ms with 1 errors (1 skipped): 5 pushed, 8 pulled");
+    }
+
+    #[test]
+    fn test_skipped_tables_fail_a_run() {
+        let report = SyncReport {
+            tables: vec![table("notes", 0, 0, None)],
+            skipped_tables: vec!["tags".to_string()],
+            fk_violations: vec![ForeignKeyViolation { table: "notes".into(), rowid: Some(1), parent: "folders".into(), fk_index: 0 }],
+            cancelled: true,
+            duration_ms: 3,
+        };
+        assert!(!report.is_success());
+        assert_eq!(
+            report.summary(),
+            "Synced 1 tables in 3 ms with 0 errors (1 skipped): 1 pushed, 2 pulled, 1 foreign key violations (cancelled)"
+        );
+    }
+}
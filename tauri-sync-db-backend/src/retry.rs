@@ -0,0 +1,122 @@
+//! Retries for remote calls
+//!
+//! Transient failures (network errors, rate limiting, 5xx) are retried with jittered
+//! exponential backoff; everything else, in particular auth failures, fails immediately.
+
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::remote::RemoteError;
+
+/// How often and how patiently failed remote calls are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each following one.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff delay.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self { max_retries: 0, ..Self::default() }
+    }
+
+    /// Backoff before retry number `retry` (0-based): a random delay between half and all of
+    /// `initial_backoff * 2^retry`, capped at `max_backoff`.
+    fn backoff(&self, retry: u32) -> Duration {
+        let full = self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        let half = full / 2;
+        half + half.mul_f64(jitter())
+    }
+
+    /// Delay before retrying after `error`, or `None` when it must not be retried.
+    /// A server-provided `Retry-After` takes precedence over the backoff, capped at `max_backoff`.
+    fn delay(&self, retry: u32, error: &RemoteError) -> Option<Duration> {
+        if retry >= self.max_retries || !error.is_retryable() {
+            return None;
+        }
+        Some(error.retry_after().map_or_else(|| self.backoff(retry), |after| after.min(self.max_backoff)))
+    }
+
+    /// Run `op` until it succeeds, fails with a non-retryable error, or the retries are used up.
+    /// Only use this for idempotent operations: a failed attempt may still have been applied.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, RemoteError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RemoteError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    let Some(delay) = self.delay(retry, &e) else {
+                        return Err(e);
+                    };
+                    retry += 1;
+                    eprintln!(
+                        "Remote call failed ({}), retry {}/{} in {:?}",
+                        e, retry, self.max_retries, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+/// Random fraction in `[0, 1)`, seeded by the std hasher's per-instance random keys.
+fn jitter() -> f64 {
+    let bits = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http(status: u16, retry_after: Option<Duration>) -> RemoteError {
+        RemoteError::Http { status, body: String::new(), retry_after }
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy { max_retries: 10, initial_backoff: Duration::from_secs(1), max_backoff: Duration::from_secs(5) };
+        for (retry, full) in [(0, 1), (1, 2), (2, 4), (3, 5), (9, 5)] {
+            let full = Duration::from_secs(full);
+            let delay = policy.backoff(retry);
+            assert!(delay >= full / 2 && delay <= full, "retry {}: {:?}", retry, delay);
+        }
+    }
+
+    #[test]
+    fn test_delay_follows_error_kind() {
+        let policy = RetryPolicy::default();
+        assert!(policy.delay(0, &RemoteError::Network("timeout".into())).is_some());
+        assert!(policy.delay(0, &http(503, None)).is_some());
+        assert_eq!(policy.delay(0, &http(429, Some(Duration::from_secs(7)))), Some(Duration::from_secs(7)));
+        assert_eq!(policy.delay(0, &http(429, Some(Duration::from_secs(86_400)))), Some(policy.max_backoff));
+
+        assert_eq!(policy.delay(0, &http(401, None)), None);
+        assert_eq!(policy.delay(0, &RemoteError::Statement { message: "no such table".into(), sql: "SELECT".into(), index: Some(0) }), None);
+        assert_eq!(policy.delay(policy.max_retries, &http(503, None)), None);
+        assert_eq!(RetryPolicy::none().delay(0, &http(503, None)), None);
+    }
+}
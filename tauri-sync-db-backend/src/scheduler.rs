@@ -0,0 +1,388 @@
+//! Background sync scheduling
+//!
+//! [`SyncScheduler`] runs `sync_all` on an interval, after a number of local writes and when
+//! the app resumes. The app spawns [`SyncScheduler::run`] on its async runtime and keeps a
+//! clone of the scheduler to report writes and lifecycle events:
+//!
+//! ```ignore
+//! let scheduler = SyncScheduler::new(SchedulerConfig::default());
+//! tauri::async_runtime::spawn(scheduler.clone().run(state.clone(), schema, client, SyncOptions::default()));
+//! app.manage(scheduler);
+//! ```
+//!
+//! Runs go through the same single-flight lock as manual syncs, so they never overlap.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tauri_plugin_http::reqwest;
+use tokio::sync::Notify;
+
+use crate::backend::DbState;
+use crate::error::SyncDbError;
+use crate::progress::CancelToken;
+use crate::report::SyncReport;
+use crate::sync::{sync_all_with_options, SyncOptions, SyncSchema};
+
+/// When the scheduler syncs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulerConfig {
+    /// Sync periodically. `None` only syncs on writes, resume and explicit requests.
+    pub interval: Option<Duration>,
+    /// Sync once this many local writes were reported. `None` ignores writes.
+    pub write_threshold: Option<usize>,
+    /// After the write threshold is reached, wait until no write was reported for this long.
+    pub debounce: Duration,
+    /// Sync when the app reports it was resumed.
+    pub sync_on_resume: bool,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(300)),
+            write_threshold: Some(20),
+            debounce: Duration::from_secs(2),
+            sync_on_resume: true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    notify: Notify,
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    /// Set by requests that skip the write debounce.
+    immediate: AtomicBool,
+    pending_writes: AtomicUsize,
+    last_report: Mutex<Option<SyncReport>>,
+    /// Cancels the sync currently run by the scheduler.
+    current: Mutex<Option<CancelToken>>,
+}
+
+/// Handle to a background sync loop. Cheap to clone; clones control the same loop.
+#[derive(Clone)]
+pub struct SyncScheduler {
+    config: SchedulerConfig,
+    shared: Arc<Shared>,
+}
+
+impl SyncScheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self { config, shared: Arc::default() }
+    }
+
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+
+    /// Report local writes. Triggers a debounced sync once the write threshold is reached.
+    pub fn record_writes(&self, count: usize) {
+        let pending = self.shared.pending_writes.fetch_add(count, Ordering::SeqCst) + count;
+        if self.config.write_threshold.is_some_and(|threshold| pending >= threshold) {
+            self.shared.notify.notify_one();
+        }
+    }
+
+    /// Number of writes reported since the last successful sync.
+    pub fn pending_writes(&self) -> usize {
+        self.shared.pending_writes.load(Ordering::SeqCst)
+    }
+
+    /// Report that the app came back to the foreground.
+    pub fn app_resumed(&self) {
+        if self.config.sync_on_resume {
+            self.sync_now();
+        }
+    }
+
+    /// Sync as soon as possible, without waiting for the interval or debounce.
+    pub fn sync_now(&self) {
+        self.shared.immediate.store(true, Ordering::SeqCst);
+        self.shared.notify.notify_one();
+    }
+
+    /// Stop starting new syncs until [`resume`](Self::resume). A running sync finishes.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume after [`pause`](Self::pause), syncing right away if writes piled up meanwhile.
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        self.record_writes(0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    /// Cancel the scheduled sync currently running, if any.
+    pub fn cancel_current(&self) {
+        if let Some(token) = self.shared.current.lock().ok().and_then(|c| c.clone()) {
+            token.cancel();
+        }
+    }
+
+    /// End the loop started by [`run`](Self::run), cancelling a running sync.
+    pub fn stop(&self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        self.cancel_current();
+        self.shared.notify.notify_one();
+    }
+
+    /// Report of the last sync started by the scheduler.
+    pub fn last_report(&self) -> Option<SyncReport> {
+        self.shared.last_report.lock().ok().and_then(|r| r.clone())
+    }
+
+    /// Wait for the next trigger. Returns `false` once stopped.
+    async fn next_trigger(&self) -> bool {
+        let interval_elapsed = match self.config.interval {
+            Some(interval) => tokio::time::timeout(interval, self.shared.notify.notified()).await.is_err(),
+            None => {
+                self.shared.notify.notified().await;
+                false
+            }
+        };
+        if self.shared.stopped.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        // Let a burst of writes settle before syncing it
+        let immediate = self.shared.immediate.swap(false, Ordering::SeqCst);
+        if !interval_elapsed && !immediate {
+            loop {
+                let before = self.pending_writes();
+                tokio::time::sleep(self.config.debounce).await;
+                if self.pending_writes() == before || self.shared.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+        }
+        !self.shared.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Sync loop. Runs until [`stop`](Self::stop); does nothing while sync is not configured.
+    pub async fn run<S: SyncSchema + Send + Sync>(
+        self,
+        state: DbState,
+        schema: S,
+        client: reqwest::Client,
+        options: SyncOptions,
+    ) {
+        eprintln!("Sync scheduler started");
+        while self.next_trigger().await {
+            if self.is_paused() {
+                continue;
+            }
+            let Some(config) = state.sync_config() else {
+                continue;
+            };
+
+            let writes = self.pending_writes();
+            let mut run_options = options.clone();
+            run_options.profile = config.profile;
+            // A token per run, so cancelling one run doesn't cancel the next
+            let cancel = options.cancel.as_ref().map_or_else(CancelToken::new, CancelToken::child);
+            run_options.cancel = Some(cancel.clone());
+            if let Ok(mut current) = self.shared.current.lock() {
+                *current = Some(cancel);
+            }
+            let result = sync_all_with_options(&client, &state, &schema, &config.url, &config.token, &run_options).await;
+            if let Ok(mut current) = self.shared.current.lock() {
+                *current = None;
+            }
+            match result {
+                Ok(report) => {
+                    eprintln!("Scheduled sync: {}", report.summary());
+                    if report.is_success() {
+                        self.shared.pending_writes.fetch_sub(writes, Ordering::SeqCst);
+                    }
+                    if let Ok(mut last) = self.shared.last_report.lock() {
+                        *last = Some(report);
+                    }
+                }
+                Err(e @ SyncDbError::SyncInProgress) => eprintln!("Scheduled sync skipped: {}", e),
+                Err(e) => eprintln!("Scheduled sync failed: {}", e),
+            }
+        }
+        eprintln!("Sync scheduler stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::init_db;
+    use crate::backend::profiles::{add_profile, DEFAULT_PROFILE};
+    use crate::progress::{ProgressCallback, SyncPhase, SyncProgress};
+    use crate::sync::{sync_all, DynamicSchema};
+    use crate::test_util::{temp_db_path, MockTurso};
+    use tokio::sync::mpsc;
+
+    struct NoTables;
+
+    impl SyncSchema for NoTables {
+        fn tables(&self) -> Vec<&str> { Vec::new() }
+        fn get_columns(&self, _table: &str) -> Vec<&str> { Vec::new() }
+        fn get_pks(&self, _table: &str) -> Vec<&str> { Vec::new() }
+        fn get_column_type(&self, _table: &str, _col: &str) -> Option<String> { None }
+    }
+
+    #[test]
+    fn test_controls() {
+        let scheduler = SyncScheduler::new(SchedulerConfig { write_threshold: Some(3), ..Default::default() });
+        scheduler.record_writes(2);
+        scheduler.clone().record_writes(1);
+        assert_eq!(scheduler.pending_writes(), 3);
+
+        scheduler.pause();
+        assert!(scheduler.is_paused());
+        scheduler.resume();
+        assert!(!scheduler.is_paused());
+    }
+
+    #[test]
+    fn test_run_future_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        let scheduler = SyncScheduler::new(SchedulerConfig::default());
+        let state = DbState::new(std::env::temp_dir().join("scheduler-test.db"));
+        let future = scheduler.run(state, NoTables, reqwest::Client::new(), SyncOptions::default());
+        assert_send(&future);
+    }
+
+    async fn configured(remote: &MockTurso) -> DbState {
+        let state = init_db(&temp_db_path("scheduler")).await.unwrap();
+        state.conn.lock().await.as_ref().unwrap()
+            .execute_batch("CREATE TABLE notes (id TEXT PRIMARY KEY, title TEXT, updated_at INTEGER)").unwrap();
+        add_profile(&state, DEFAULT_PROFILE, remote.url(), remote.token().to_string()).await.unwrap();
+        state
+    }
+
+    /// Start `scheduler` on `state`, returning the end of each run.
+    async fn start(scheduler: &SyncScheduler, state: &DbState, options: SyncOptions) -> mpsc::UnboundedReceiver<SyncProgress> {
+        let (progress, events) = ProgressCallback::channel();
+        let forward = options.progress.clone();
+        let options = SyncOptions {
+            progress: Some(ProgressCallback::new(move |p| {
+                if let Some(forward) = &forward {
+                    forward.emit(p);
+                }
+                if p.phase == SyncPhase::Finished {
+                    progress.emit(p);
+                }
+            })),
+            ..options
+        };
+        let schema = DynamicSchema::load(state, vec!["notes"]).await.unwrap();
+        tokio::spawn(scheduler.clone().run(state.clone(), schema, reqwest::Client::new(), options));
+        events
+    }
+
+    async fn finished(events: &mut mpsc::UnboundedReceiver<SyncProgress>) {
+        tokio::time::timeout(Duration::from_secs(10), events.recv()).await.expect("no sync ran").unwrap();
+    }
+
+    fn idle() -> SchedulerConfig {
+        SchedulerConfig { interval: None, write_threshold: None, debounce: Duration::from_millis(20), sync_on_resume: false }
+    }
+
+    #[tokio::test]
+    async fn test_interval_syncs() {
+        let remote = MockTurso::start().await.unwrap();
+        let state = configured(&remote).await;
+        let scheduler = SyncScheduler::new(SchedulerConfig { interval: Some(Duration::from_millis(50)), ..idle() });
+        let mut events = start(&scheduler, &state, SyncOptions::default()).await;
+        finished(&mut events).await;
+        finished(&mut events).await;
+        assert!(scheduler.last_report().unwrap().is_success());
+        scheduler.stop();
+    }
+
+    #[tokio::test]
+    async fn test_write_threshold_syncs_after_debounce() {
+        let remote = MockTurso::start().await.unwrap();
+        let state = configured(&remote).await;
+        let scheduler = SyncScheduler::new(SchedulerConfig { write_threshold: Some(2), ..idle() });
+        let mut events = start(&scheduler, &state, SyncOptions::default()).await;
+
+        scheduler.record_writes(1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(events.try_recv().is_err(), "synced below the threshold");
+        scheduler.record_writes(1);
+        finished(&mut events).await;
+        assert_eq!(scheduler.pending_writes(), 0);
+        scheduler.stop();
+    }
+
+    #[tokio::test]
+    async fn test_resume_syncs() {
+        let remote = MockTurso::start().await.unwrap();
+        let state = configured(&remote).await;
+        let scheduler = SyncScheduler::new(SchedulerConfig { sync_on_resume: true, ..idle() });
+        let mut events = start(&scheduler, &state, SyncOptions::default()).await;
+        scheduler.app_resumed();
+        finished(&mut events).await;
+        assert!(scheduler.last_report().is_some());
+        scheduler.stop();
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_a_run_spares_the_next() {
+        let remote = MockTurso::start().await.unwrap();
+        let state = configured(&remote).await;
+        let scheduler = SyncScheduler::new(idle());
+        let canceller = scheduler.clone();
+        let first = Arc::new(AtomicBool::new(true));
+        let caller = CancelToken::new();
+        let options = SyncOptions {
+            progress: Some(ProgressCallback::new(move |p| {
+                if p.phase == SyncPhase::SchemaCheck && first.swap(false, Ordering::SeqCst) {
+                    canceller.cancel_current();
+                }
+            })),
+            cancel: Some(caller.clone()),
+            ..Default::default()
+        };
+        let mut events = start(&scheduler, &state, options).await;
+
+        scheduler.sync_now();
+        finished(&mut events).await;
+        assert!(scheduler.last_report().unwrap().cancelled);
+        scheduler.sync_now();
+        finished(&mut events).await;
+        let report = scheduler.last_report().unwrap();
+        assert!(!report.cancelled && report.is_success(), "{}", report.summary());
+
+        // The caller's token still cancels every run
+        caller.cancel();
+        scheduler.sync_now();
+        finished(&mut events).await;
+        assert!(scheduler.last_report().unwrap().cancelled);
+        scheduler.stop();
+    }
+
+    #[tokio::test]
+    async fn test_manual_sync_during_scheduled_sync() {
+        let remote = MockTurso::start().await.unwrap();
+        let state = configured(&remote).await;
+        let scheduler = SyncScheduler::new(idle());
+        let mut events = start(&scheduler, &state, SyncOptions::default()).await;
+
+        // Hold the connection, so the scheduled run waits for it once started
+        let conn_guard = state.conn.lock().await;
+        scheduler.sync_now();
+        while state.sync_lock.try_lock().is_ok() {
+            tokio::task::yield_now().await;
+        }
+        let err = sync_all(&reqwest::Client::new(), &state, &NoTables, &remote.url(), remote.token()).await.unwrap_err();
+        assert_eq!(err, SyncDbError::SyncInProgress);
+        drop(conn_guard);
+        finished(&mut events).await;
+        assert!(scheduler.last_report().unwrap().is_success());
+        scheduler.stop();
+    }
+}
@@ -0,0 +1,219 @@
+//! Secret storage
+//!
+//! The Turso token is kept out of `sync_config.json` in a [`SecretStore`]. Apps can plug in
+//! the platform keychain; the default [`EncryptedFileStore`] seals secrets with AES-256-GCM
+//! under a key derived (HKDF-SHA256) from a device secret.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::SyncDbError;
+
+/// File of the default store, next to the database.
+pub const SECRETS_FILE: &str = "sync_secrets.enc";
+/// Random device secret of the default store, next to the database.
+pub const DEVICE_SECRET_FILE: &str = "sync_device.key";
+
+const KEY_SALT: &[u8] = b"tauri-sync-db secret store";
+const KEY_INFO: &[u8] = b"aes-256-gcm v1";
+
+/// Storage for small secrets such as auth tokens.
+pub trait SecretStore: Send + Sync {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncDbError>;
+    fn set(&self, name: &str, value: &str) -> Result<(), SyncDbError>;
+    fn delete(&self, name: &str) -> Result<(), SyncDbError>;
+}
+
+/// Where the device secret of an [`EncryptedFileStore`] comes from.
+enum DeviceSecret {
+    Bytes(Vec<u8>),
+    /// Random bytes, generated on first use.
+    File(PathBuf),
+}
+
+/// Secrets encrypted into a single file.
+///
+/// The file holds a random nonce followed by the sealed JSON map of all secrets.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    device_secret: DeviceSecret,
+    /// Serializes read-modify-write cycles of the file.
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    /// Store at `path`, keyed by a secret the app keeps elsewhere, e.g. in the OS keystore.
+    pub fn new(path: impl Into<PathBuf>, device_secret: impl Into<Vec<u8>>) -> Self {
+        Self { path: path.into(), device_secret: DeviceSecret::Bytes(device_secret.into()), lock: Mutex::new(()) }
+    }
+
+    /// Default store in `dir`, keyed by a random device secret file created there with
+    /// owner-only permissions. Keeps the token out of the config file, backups of it and logs;
+    /// it does not protect against someone who can read the whole app directory.
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            path: dir.join(SECRETS_FILE),
+            device_secret: DeviceSecret::File(dir.join(DEVICE_SECRET_FILE)),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn key(&self) -> Result<LessSafeKey, SyncDbError> {
+        let secret = match &self.device_secret {
+            DeviceSecret::Bytes(bytes) => bytes.clone(),
+            DeviceSecret::File(path) => load_or_create_device_secret(path)?,
+        };
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, KEY_SALT).extract(&secret);
+        let okm = prk.expand(&[KEY_INFO], &AES_256_GCM)
+            .map_err(|_| config_error("Failed to derive secret store key"))?;
+        Ok(LessSafeKey::new(UnboundKey::from(okm)))
+    }
+
+    fn read(&self, key: &LessSafeKey) -> Result<BTreeMap<String, String>, SyncDbError> {
+        let mut data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(SyncDbError::Io { message: format!("Failed to read {}: {}", self.path.display(), e) }),
+        };
+        if data.len() < NONCE_LEN {
+            return Err(config_error(format!("Secret store {} is truncated", self.path.display())));
+        }
+        let mut sealed = data.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&data).map_err(|_| config_error("Invalid nonce"))?;
+        let plain = key.open_in_place(nonce, Aad::from(KEY_INFO), &mut sealed)
+            .map_err(|_| config_error(format!("Secret store {} can't be decrypted with this device's key", self.path.display())))?;
+        serde_json::from_slice(plain).map_err(|e| config_error(format!("Invalid secret store {}: {}", self.path.display(), e)))
+    }
+
+    fn write(&self, key: &LessSafeKey, secrets: &BTreeMap<String, String>) -> Result<(), SyncDbError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| config_error("Failed to generate nonce"))?;
+        let mut sealed = serde_json::to_vec(secrets).map_err(|e| config_error(e.to_string()))?;
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(KEY_INFO), &mut sealed)
+            .map_err(|_| config_error("Failed to encrypt secrets"))?;
+
+        let mut data = nonce.to_vec();
+        data.append(&mut sealed);
+        write_private(&self.path, &data)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BTreeMap<String, String>)) -> Result<(), SyncDbError> {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = self.key()?;
+        let mut secrets = self.read(&key)?;
+        f(&mut secrets);
+        self.write(&key, &secrets)
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncDbError> {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        Ok(self.read(&self.key()?)?.remove(name))
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), SyncDbError> {
+        self.update(|secrets| {
+            secrets.insert(name.to_string(), value.to_string());
+        })
+    }
+
+    fn delete(&self, name: &str) -> Result<(), SyncDbError> {
+        self.update(|secrets| {
+            secrets.remove(name);
+        })
+    }
+}
+
+fn load_or_create_device_secret(path: &Path) -> Result<Vec<u8>, SyncDbError> {
+    match fs::read(path) {
+        Ok(secret) if !secret.is_empty() => return Ok(secret),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(SyncDbError::Io { message: format!("Failed to read device secret: {}", e) }),
+    }
+    let mut secret = vec![0u8; 32];
+    SystemRandom::new().fill(&mut secret).map_err(|_| config_error("Failed to generate device secret"))?;
+    write_private(path, &secret)?;
+    Ok(secret)
+}
+
+/// Replace a file with one readable by the owner only. The data goes to a temporary file
+/// renamed over `path`, so a crash never leaves a truncated file behind.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<(), SyncDbError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let write_error = |e: std::io::Error| SyncDbError::Io { message: format!("Failed to write {}: {}", path.display(), e) };
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(write_error)?;
+    // The mode only applies to new files; a leftover temporary file keeps its own
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600)).map_err(write_error)?;
+    }
+    file.write_all(data).map_err(write_error)?;
+    file.sync_all().map_err(write_error)?;
+    fs::rename(&tmp, path).map_err(write_error)
+}
+
+fn config_error(message: impl Into<String>) -> SyncDbError {
+    SyncDbError::Config { message: message.into() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_db_path;
+
+    #[test]
+    fn test_encrypted_file_store() {
+        let dir = temp_db_path("secrets").parent().unwrap().to_path_buf();
+        let store = EncryptedFileStore::in_dir(&dir);
+        assert_eq!(store.get("token").unwrap(), None);
+
+        store.set("token", "secret-token").unwrap();
+        store.set("other", "x").unwrap();
+        assert_eq!(store.get("token").unwrap().as_deref(), Some("secret-token"));
+        let raw = fs::read(dir.join(SECRETS_FILE)).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("secret-token"));
+
+        // Another key can't read it
+        let foreign = EncryptedFileStore::new(dir.join(SECRETS_FILE), b"another device".to_vec());
+        assert!(foreign.get("token").is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [SECRETS_FILE, DEVICE_SECRET_FILE] {
+                assert_eq!(fs::metadata(dir.join(file)).unwrap().permissions().mode() & 0o777, 0o600, "{}", file);
+            }
+        }
+
+        store.delete("token").unwrap();
+        assert_eq!(EncryptedFileStore::in_dir(&dir).get("token").unwrap(), None);
+        assert_eq!(store.get("other").unwrap().as_deref(), Some("x"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,11 +3,24 @@
 //! This module provides a reusable implementation of the generic syncing protocol.
 //! Applications must implement the `SyncSchema` trait to define their specific tables.
 
-use crate::backend::{DbState, query_strings, execute_sql};
+use crate::backend::{DbState, execute_sql, query_rows};
 use tauri_plugin_http::reqwest;
-use serde_json::{json, Value};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use crate::error::SyncDbError;
+use crate::backend::metadata::{ensure_metadata, is_metadata_table, DELETE_LOG_DDL, DELETE_LOG_HLC_DDL};
+use crate::backend::profiles::DEFAULT_PROFILE;
+use crate::conflict::{record_conflict, Conflict, ConflictResolver, Resolution};
+use crate::migrations::{apply_remote_migrations, Migrations, REMOTE_MIGRATIONS_KEY};
+use crate::remote::{RemoteDb, RemoteStatement};
+use crate::progress::{CancelToken, ProgressCallback, SyncPhase, SyncProgress};
+use crate::retry::RetryPolicy;
+use crate::hlc::{Hlc, HlcClock, WallClock, HLC_COLUMN};
+use crate::remote_schema::{plan_migration, read_local_tables, remote_columns, schema_hash, REMOTE_COLUMNS_SQL, REMOTE_INDEXES_SQL, SCHEMA_HASH_KEY};
+use crate::report::{ForeignKeyViolation, SyncReport, TableReport};
+use crate::value::{Row, SyncValue};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
 
 /// Trait to define the schema for synchronization.
 pub trait SyncSchema {
@@ -25,650 +38,1972 @@ pub trait SyncSchema {
     /// Get the type of a specific column.
     /// Returns the type string (e.g., "INTEGER", "TEXT") if validation is needed.
     fn get_column_type(&self, table: &str, col: &str) -> Option<String>;
+
+    /// Policy for rows changed both locally and on the remote since the last sync.
+    fn conflict_resolver(&self, _table: &str) -> ConflictResolver {
+        ConflictResolver::LastWriterWins
+    }
+
+    /// Tables `table` references through foreign keys, or `None` when unknown.
+    /// A strict sync skips the tables depending on a failed one; all later tables when unknown.
+    fn parents(&self, _table: &str) -> Option<Vec<&str>> {
+        None
+    }
+}
+
+/// How local changes are detected for pushing.
+///
+/// Either way, a row is pushed when the profile's remote doesn't have its current version: app
+/// writes clear the row's HLC (see [`install_hlc_columns`]), and the versions each remote has
+/// are kept in `_sync_pushed`. Apps don't need to bump `updated_at` for a write to be pushed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeTracking {
+    /// Detect writes by their cleared HLC only. Hard deletes are only propagated with
+    /// [`SyncOptions::capture_deletes`].
+    #[default]
+    Hlc,
+    /// Also record writes in `_sync_changelog` with triggers on every synced table
+    /// (see [`install_changelog`]). Before each push the log is drained: keys it lists that no
+    /// longer exist become `_sync_deletes` entries, so hard deletes of every table are propagated.
+    Changelog,
+}
+
+/// Column of the app's write timestamps, ordering rows written by clients without HLCs.
+const UPDATED_AT_COLUMN: &str = "updated_at";
+
+/// Default number of remote rows fetched per pull request.
+pub const DEFAULT_PULL_PAGE_SIZE: usize = 1000;
+/// Default number of rows sent per push request.
+pub const DEFAULT_PUSH_BATCH_SIZE: usize = 200;
+
+/// Options controlling a sync run.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Capture hard deletes with triggers for tables that have no `deleted_at` column,
+    /// and propagate them through the `_sync_deletes` log.
+    pub capture_deletes: bool,
+    /// Physically purge tombstones (soft-deleted rows and delete log entries) older than
+    /// this horizon after each table sync. `None` keeps tombstones forever.
+    ///
+    /// A device that stays offline for longer than the horizon can resurrect purged rows, and a
+    /// profile not synced for that long misses purged deletes, so keep this comfortably above
+    /// the longest expected offline period.
+    pub tombstone_retention: Option<chrono::Duration>,
+    /// Skip the tables depending on a failed table, instead of syncing them anyway.
+    /// Tables referencing a failed one through foreign keys would be left with dangling
+    /// references. Skipped tables are listed in [`SyncReport::skipped_tables`].
+    pub strict: bool,
+    /// How local writes and deletes are detected; [`ChangeTracking::Hlc`] by default.
+    pub change_tracking: ChangeTracking,
+    /// Remote rows fetched per request. Pulls page through the table in `(sync_hlc, pk)` order,
+    /// followed by rows without an HLC in `(updated_at, pk)` order, and persist their position,
+    /// so an interrupted pull resumes where it stopped.
+    pub pull_page_size: usize,
+    /// How far before the newest HLC already pulled the next pull starts, 5 minutes by default.
+    /// HLCs are generated by the devices, so a row can reach the remote after rows with a higher
+    /// HLC were pulled, e.g. when two devices push concurrently; the overlap still fetches it.
+    /// Rows already applied are skipped.
+    pub pull_overlap: chrono::Duration,
+    /// Rows sent per push request.
+    pub push_batch_size: usize,
+    /// App migrations to apply to the remote before syncing, keeping it on the same
+    /// schema as the local database. Skipped once the remote is known to be at their latest version.
+    pub remote_migrations: Option<Migrations>,
+    /// Retries of failed remote reads and pushes. Auth and SQL errors are never retried.
+    pub retry: RetryPolicy,
+    /// Receives progress events during the run.
+    pub progress: Option<ProgressCallback>,
+    /// Stops the run between batches once cancelled; see [`SyncReport::cancelled`].
+    pub cancel: Option<CancelToken>,
+    /// Sync profile of the remote, whose watermarks in `sync_status` this run reads and advances.
+    pub profile: String,
+    /// Time source of the HLCs stamped during the run; `None` uses the system clock.
+    /// Lets simulations replay a run exactly.
+    pub wall_clock: Option<WallClock>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TursoError {
-    message: String,
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            capture_deletes: false,
+            tombstone_retention: None,
+            strict: false,
+            change_tracking: ChangeTracking::default(),
+            pull_page_size: DEFAULT_PULL_PAGE_SIZE,
+            pull_overlap: chrono::Duration::minutes(5),
+            push_batch_size: DEFAULT_PUSH_BATCH_SIZE,
+            remote_migrations: None,
+            retry: RetryPolicy::default(),
+            progress: None,
+            cancel: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            wall_clock: None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TursoResultSet {
+/// Per-table information resolved from the schema before syncing.
+struct TableSpec {
+    name: String,
+    /// Position in the sync order.
+    index: usize,
     columns: Vec<String>,
-    rows: Vec<Vec<Value>>,
+    pks: Vec<String>,
+    /// Positions of the primary key columns in `columns`.
+    pk_idx: Vec<usize>,
+    updated_at_type: String,
+    /// Type of the `deleted_at` column, if the table uses soft-delete tombstones.
+    deleted_at_type: Option<String>,
+    /// Whether hard deletes are propagated through `_sync_deletes`.
+    log_deletes: bool,
+    use_changelog: bool,
+    resolver: ConflictResolver,
+}
+
+impl TableSpec {
+    fn from_schema<S: SyncSchema>(schema: &S, table: &str, options: &SyncOptions) -> Self {
+        let deleted_at_type = schema.get_column_type(table, "deleted_at");
+        let pks: Vec<String> = schema.get_pks(table).iter().map(|s| s.to_string()).collect();
+        // The HLC column is managed by the library and may be missing from a schema loaded before it was added
+        let mut columns: Vec<String> = schema.get_columns(table).iter()
+            .filter(|c| **c != HLC_COLUMN)
+            .map(|s| s.to_string())
+            .collect();
+        columns.push(HLC_COLUMN.to_string());
+        let pk_idx = pks.iter()
+            .filter_map(|pk| columns.iter().position(|c| c == pk))
+            .collect();
+        let use_changelog = options.change_tracking == ChangeTracking::Changelog;
+        Self {
+            name: table.to_string(),
+            index: 0,
+            columns,
+            log_deletes: !pks.is_empty() && (use_changelog || (options.capture_deletes && deleted_at_type.is_none())),
+            use_changelog,
+            pks,
+            pk_idx,
+            updated_at_type: schema.get_column_type(table, UPDATED_AT_COLUMN).unwrap_or("TEXT".to_string()),
+            deleted_at_type,
+            resolver: schema.conflict_resolver(table),
+        }
+    }
+
+    /// Position of the HLC column, always the last synced column.
+    fn hlc_idx(&self) -> usize {
+        self.columns.len() - 1
+    }
+
+    fn updated_at_idx(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c == UPDATED_AT_COLUMN)
+    }
+
+    /// Condition on a row to be removed by a delete logged at `?`: a row written after the
+    /// delete is kept. `None` when the table has no timestamp to compare.
+    fn deleted_since_clause(&self) -> Option<String> {
+        self.updated_at_idx().map(|_| format!("({col} IS NULL OR {col} <= ?)", col = UPDATED_AT_COLUMN))
+    }
+
+    /// Primary key of a row as a JSON array, the same encoding the delete log uses.
+    fn row_key(&self, row: &Row) -> String {
+        serde_json::Value::Array(self.pk_idx.iter().map(|&i| row[i].to_json()).collect()).to_string()
+    }
+
+    fn check_pks(&self) -> Result<(), SyncDbError> {
+        if self.pks.is_empty() || self.pk_idx.len() != self.pks.len() {
+            return Err(SyncDbError::Schema { message: format!("Primary key of table {} is not part of its synced columns", self.name) });
+        }
+        Ok(())
+    }
+}
+
+/// Rows whose current version the remote of the synced profile doesn't have yet.
+struct LocalChanges {
+    rows: Vec<Row>,
+}
+
+/// One page of remote changes.
+struct RemotePage {
+    rows: Vec<Row>,
+    /// `(sync_hlc, updated_at, pk...)` of the last row fetched, where the next page starts.
+    cursor: Option<Row>,
+    /// Whether this was the last page.
+    last: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-enum TursoItemResponse {
-    Success { results: TursoResultSet },
-    Error { error: TursoError },
+/// Keys whose version was chosen by conflict resolution and bypasses the last-writer-wins checks.
+#[derive(Default)]
+struct ForcedKeys {
+    push: HashSet<String>,
+    pull: HashSet<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TursoResponse {
-    results: Vec<TursoItemResponse>,
+/// Shared state for one sync run.
+struct SyncContext<'a> {
+    state: &'a DbState,
+    remote: RemoteDb,
+    options: &'a SyncOptions,
+    clock: HlcClock,
+    table_count: usize,
 }
 
-// Internal unified result for our logic
-struct TursoResult {
-    rows: Option<Vec<Vec<Value>>>,
-    error: Option<String>,
+impl SyncContext<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.options.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    fn check_cancelled(&self) -> Result<(), SyncDbError> {
+        if self.is_cancelled() { Err(SyncDbError::Cancelled) } else { Ok(()) }
+    }
+
+    fn progress(&self, phase: SyncPhase, spec: Option<&TableSpec>, rows: usize) {
+        if let Some(progress) = &self.options.progress {
+            progress.emit(&SyncProgress {
+                phase,
+                table: spec.map(|s| s.name.clone()),
+                table_index: spec.map_or(0, |s| s.index),
+                table_count: self.table_count,
+                rows,
+            });
+        }
+    }
+    async fn remote_rows(&self, stmt: RemoteStatement) -> Result<Vec<Row>, SyncDbError> {
+        Ok(self.options.retry.run(|| self.remote.query(stmt.clone())).await?)
+    }
+
+    /// Run statements in one remote transaction, sent `push_batch_size` at a time.
+    /// The transaction is retried as a whole, so the statements must be idempotent.
+    async fn remote_batch(&self, statements: &[RemoteStatement]) -> Result<(), SyncDbError> {
+        if statements.is_empty() {
+            return Ok(());
+        }
+        let batch_size = self.options.push_batch_size.max(1);
+        self.options.retry.run(|| async move {
+            let mut tx = self.remote.transaction();
+            for (n, chunk) in statements.chunks(batch_size).enumerate() {
+                tx.execute(chunk.to_vec()).await.map_err(|e| e.offset_statement(n * batch_size))?;
+            }
+            tx.commit().await
+        }).await?;
+        Ok(())
+    }
+}
+
+fn is_int_type(col_type: &str) -> bool {
+    col_type.to_uppercase().contains("INT")
+}
+
+/// Convert a timestamp string into a typed parameter matching the column type.
+fn time_value(value: &str, col_type: &str) -> SyncValue {
+    if is_int_type(col_type) {
+        value.parse::<i64>().map(SyncValue::Integer).unwrap_or_else(|_| SyncValue::Text(value.to_string()))
+    } else {
+        SyncValue::Text(value.to_string())
+    }
 }
 
-struct TursoResponseUnified {
-    results: Vec<TursoResult>,
+/// Format a point in time the way `updated_at`/`deleted_at` values are written for this column type.
+fn format_time(t: chrono::DateTime<chrono::Local>, col_type: &str) -> String {
+    if is_int_type(col_type) {
+        t.timestamp_millis().to_string()
+    } else {
+        t.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
 }
 
 /// Orchestrates the full sync process for all tables.
+///
+/// Per-table failures, and tables strict mode skipped because of them, are recorded in the
+/// returned [`SyncReport`]; an `Err` means the run could not start (e.g. [`SyncDbError::Auth`]
+/// from the remote schema check, or [`SyncDbError::SyncInProgress`]).
 pub async fn sync_all<S: SyncSchema + Send + Sync>(
     client: &reqwest::Client,
     state: &DbState,
     schema: &S,
     url: &str,
     token: &str,
-) -> Result<(), String> {
+) -> Result<SyncReport, SyncDbError> {
+    sync_all_with_options(client, state, schema, url, token, &SyncOptions::default()).await
+}
+
+/// Same as [`sync_all`], with explicit [`SyncOptions`].
+pub async fn sync_all_with_options<S: SyncSchema + Send + Sync>(
+    client: &reqwest::Client,
+    state: &DbState,
+    schema: &S,
+    url: &str,
+    token: &str,
+    options: &SyncOptions,
+) -> Result<SyncReport, SyncDbError> {
+    let _sync_guard = state.sync_lock.try_lock().map_err(|_| SyncDbError::SyncInProgress)?;
     eprintln!("Starting cloud sync...");
+    let started = std::time::Instant::now();
+    let mut report = SyncReport::default();
     
-    // 1. Verify remote schema
-    ensure_remote_schema(client, state, schema, url, token).await?;
-    
+    if options.capture_deletes {
+        install_delete_log(state, schema).await?;
+    }
+    if options.change_tracking == ChangeTracking::Changelog {
+        install_changelog(state, schema).await?;
+    }
+    install_hlc_columns(state, schema).await?;
+
+    let clock = {
+        let conn_guard = state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        ensure_metadata(conn)?;
+        let clock = HlcClock::load(conn)?;
+        match &options.wall_clock {
+            Some(wall) => clock.with_wall_clock(wall.clone()),
+            None => clock,
+        }
+    };
     let tables = schema.tables();
-    // 2. Sequential sync for each table (to respect FK dependencies)
-    // We strictly follow the order defined in schema.tables()
+    let ctx = SyncContext {
+        state,
+        remote: RemoteDb::new(client.clone(), url, token),
+        options,
+        clock,
+        table_count: tables.len(),
+    };
+
+    // 1. Verify remote schema
+    ctx.progress(SyncPhase::SchemaCheck, None, 0);
+    prepare_remote(&ctx, schema).await?;
     
-    for table_name in tables {
-        let table = table_name.to_string();
-        let client = client.clone();
-        let state = state.clone();
-        let url = url.to_string();
-        let token = token.to_string();
-        
-        let columns: Vec<String> = schema.get_columns(&table).iter().map(|s| s.to_string()).collect();
-        let pks: Vec<String> = schema.get_pks(&table).iter().map(|s| s.to_string()).collect();
-        let updated_at_type = schema.get_column_type(&table, "updated_at").unwrap_or("TEXT".to_string());
+    // 2. Sequential sync for each table, in the order of schema.tables(), so parents
+    // are synced before the tables referencing them
+    let mut failed: HashSet<&str> = HashSet::new();
+    let mut reverified = false;
+    for (i, table_name) in tables.iter().enumerate() {
+        if options.strict && !failed.is_empty() {
+            let depends_on_failed = match schema.parents(table_name) {
+                Some(parents) => parents.iter().any(|p| p != table_name && failed.contains(p)),
+                None => true,
+            };
+            if depends_on_failed {
+                eprintln!("Skipping table {}, it depends on a failed table", table_name);
+                failed.insert(table_name);
+                report.skipped_tables.push(table_name.to_string());
+                continue;
+            }
+        }
+        if ctx.is_cancelled() {
+            eprintln!("Sync cancelled before table {}", table_name);
+            report.cancelled = true;
+            report.skipped_tables.extend(tables[i..].iter().map(|t| t.to_string()));
+            break;
+        }
+        let mut spec = TableSpec::from_schema(schema, table_name, options);
+        spec.index = i;
+        let mut table_report = TableReport::new(&spec.name);
+        let table_started = std::time::Instant::now();
         
         // Execute sequentially
-        if let Err(e) = sync_table(&client, &state, &url, &token, &table, &columns, &pks, &updated_at_type).await {
-            eprintln!("Table sync failed for {}: {}", table, e);
-            // We can choose to abort or continue. For now, let's collect error but continue other tables? 
-            // Actually, if dependencies fail, downstream might fail too. 
-            // But let's try to do as much as possible.
-            // return Err(e); // strict mode
+        let mut result = sync_table(&ctx, &spec, &mut table_report).await;
+        // The remote lost tables or columns since it was verified, e.g. it was recreated
+        if !reverified && result.as_ref().is_err_and(is_missing_remote_schema) {
+            eprintln!("Remote schema of {} is out of date, verifying it again", spec.name);
+            reverified = true;
+            result = match forget_remote_schema(state).await {
+                Ok(()) => prepare_remote(&ctx, schema).await,
+                Err(e) => Err(e),
+            };
+            if result.is_ok() {
+                result = sync_table(&ctx, &spec, &mut table_report).await;
+            }
+        }
+        table_report.duration_ms = table_started.elapsed().as_millis() as u64;
+
+        if let Err(e) = result {
+            eprintln!("Table sync failed for {}: {}", spec.name, e);
+            table_report.error = Some(e.to_string());
+            report.tables.push(table_report);
+
+            if e == SyncDbError::Cancelled {
+                report.cancelled = true;
+                report.skipped_tables.extend(tables[i + 1..].iter().map(|t| t.to_string()));
+                break;
+            }
+            failed.insert(table_name);
+            continue;
+        }
+        report.tables.push(table_report);
+    }
+
+    // 3. Report references left dangling, e.g. by a parent deleted on another device
+    let synced: Vec<&str> = report.tables.iter().map(|t| t.table.as_str()).collect();
+    match check_foreign_keys(state, &synced).await {
+        Ok(violations) => report.fk_violations = violations,
+        Err(e) => eprintln!("Warning: foreign key check failed: {}", e),
+    }
+
+    report.duration_ms = started.elapsed().as_millis() as u64;
+    ctx.progress(SyncPhase::Finished, None, report.total_pushed() + report.total_pulled());
+    eprintln!("Cloud sync finished. {}", report.summary());
+    Ok(report)
+}
+
+/// Run `PRAGMA foreign_key_check` on each table.
+///
+/// Remote rows are applied with foreign keys disabled, so children can arrive before their
+/// parents (self-references, cycles); this catches references that are still dangling.
+async fn check_foreign_keys(state: &DbState, tables: &[&str]) -> Result<Vec<ForeignKeyViolation>, SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    let mut violations = Vec::new();
+    for table in tables {
+        let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_check({})", table))?;
+        let rows = stmt.query_map([], |r| Ok(ForeignKeyViolation {
+            table: r.get(0)?,
+            rowid: r.get(1)?,
+            parent: r.get(2)?,
+            fk_index: r.get(3)?,
+        }))?;
+        for violation in rows {
+            violations.push(violation?);
         }
     }
 
-    // No tasks to await anymore
-    let errors = Vec::<String>::new();
+    if !violations.is_empty() {
+        eprintln!("Warning: {} rows reference missing parents after sync", violations.len());
+    }
+    Ok(violations)
+}
 
-    if !errors.is_empty() {
-        return Err(format!("Sync completed with {} errors: {:?}", errors.len(), errors));
+/// SQL expression for the current time, in the format of the table's `updated_at` column.
+fn now_sql(updated_at_type: &str) -> &'static str {
+    if is_int_type(updated_at_type) {
+        "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)"
+    } else {
+        "datetime('now', 'localtime')"
     }
-    
-    eprintln!("Cloud sync completed successfully.");
+}
+
+fn pk_json_sql(pks: &[String], prefix: &str) -> String {
+    format!(
+        "json_array({})",
+        pks.iter().map(|pk| format!("{}.{}", prefix, pk)).collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Install AFTER INSERT/UPDATE/DELETE triggers feeding `_sync_changelog` on every table of the schema.
+///
+/// Required for [`ChangeTracking::Changelog`]. Safe to call repeatedly; call it right after
+/// `init_db` so writes made before the first sync are captured too.
+pub async fn install_changelog<S: SyncSchema>(state: &DbState, schema: &S) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    for table in schema.tables() {
+        let pks: Vec<String> = schema.get_pks(table).iter().map(|s| s.to_string()).collect();
+        if pks.is_empty() {
+            eprintln!("Warning: table {} has no primary key, its changes can't be logged", table);
+            continue;
+        }
+        let updated_at_type = schema.get_column_type(table, UPDATED_AT_COLUMN).unwrap_or("TEXT".to_string());
+        let now = now_sql(&updated_at_type);
+        let new_pk = pk_json_sql(&pks, "NEW");
+        let old_pk = pk_json_sql(&pks, "OLD");
+
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS _sync_log_insert_{table} AFTER INSERT ON {table}
+             BEGIN
+                 INSERT INTO _sync_changelog (table_name, pk, op, changed_at) VALUES ('{table}', {new_pk}, 'INSERT', {now});
+             END;
+             CREATE TRIGGER IF NOT EXISTS _sync_log_update_{table} AFTER UPDATE ON {table}
+             BEGIN
+                 INSERT INTO _sync_changelog (table_name, pk, op, changed_at)
+                 SELECT '{table}', {old_pk}, 'DELETE', {now} WHERE {old_pk} IS NOT {new_pk};
+                 INSERT INTO _sync_changelog (table_name, pk, op, changed_at) VALUES ('{table}', {new_pk}, 'UPDATE', {now});
+             END;
+             CREATE TRIGGER IF NOT EXISTS _sync_log_delete_{table} AFTER DELETE ON {table}
+             BEGIN
+                 INSERT INTO _sync_changelog (table_name, pk, op, changed_at) VALUES ('{table}', {old_pk}, 'DELETE', {now});
+             END;",
+            table = table,
+            new_pk = new_pk,
+            old_pk = old_pk,
+            now = now,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Current end of the changelog, taken before the sync engine writes to the database.
+/// `None` when changelog tracking is off.
+fn changelog_mark(ctx: &SyncContext<'_>, conn: &Connection) -> Result<Option<i64>, SyncDbError> {
+    if ctx.options.change_tracking != ChangeTracking::Changelog {
+        return Ok(None);
+    }
+    conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM _sync_changelog", [], |r| r.get(0))
+        .map(Some)
+        .map_err(SyncDbError::from)
+}
+
+/// Drop changelog entries written by the sync engine itself since `mark`, so pulled rows
+/// and HLC stamps are not pushed back. Must run while still holding the connection.
+fn discard_own_changes(conn: &Connection, mark: Option<i64>) -> Result<(), SyncDbError> {
+    if let Some(mark) = mark {
+        conn.execute("DELETE FROM _sync_changelog WHERE seq > ?1", [mark])?;
+    }
+    Ok(())
+}
+
+/// Install the `_sync_deletes` triggers for every table without a `deleted_at` column.
+///
+/// Deleting a row records its primary key (as a JSON array) so the delete can be pushed to the
+/// remote and replayed on other devices. Re-inserting the same key clears the entry.
+/// Safe to call repeatedly; call it right after `init_db` so deletes made before the first
+/// sync are captured too.
+pub async fn install_delete_log<S: SyncSchema>(state: &DbState, schema: &S) -> Result<(), SyncDbError> {
+    let options = SyncOptions { capture_deletes: true, ..Default::default() };
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    for table_name in schema.tables() {
+        let spec = TableSpec::from_schema(schema, table_name, &options);
+        if !spec.log_deletes {
+            continue;
+        }
+
+        let sql = format!(
+            "CREATE TRIGGER IF NOT EXISTS _sync_delete_{table} AFTER DELETE ON {table}
+             BEGIN
+                 INSERT OR REPLACE INTO _sync_deletes (table_name, pk, deleted_at, pending)
+                 VALUES ('{table}', {pk}, {now}, 1);
+             END;
+             CREATE TRIGGER IF NOT EXISTS _sync_undelete_{table} AFTER INSERT ON {table}
+             BEGIN
+                 DELETE FROM _sync_deletes WHERE table_name = '{table}' AND pk = {new_pk};
+             END;",
+            table = spec.name,
+            pk = pk_json_sql(&spec.pks, "OLD"),
+            now = now_sql(&spec.updated_at_type),
+            new_pk = pk_json_sql(&spec.pks, "NEW"),
+        );
+        conn.execute_batch(&sql)?;
+    }
+
+    Ok(())
+}
+
+/// Add the `sync_hlc` column to every synced table and install the trigger that clears it on app writes.
+///
+/// A NULL HLC marks a row as changed locally; it gets a fresh timestamp from the device clock
+/// when it is pushed. Writes made by the sync engine set the column explicitly and are left alone.
+pub async fn install_hlc_columns<S: SyncSchema>(state: &DbState, schema: &S) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    for table in schema.tables() {
+        let pks = schema.get_pks(table);
+        if pks.is_empty() {
+            continue;
+        }
+
+        let has_column: bool = conn.query_row(
+            &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?", table),
+            [HLC_COLUMN],
+            |r| r.get(0),
+        )?;
+        if !has_column {
+            execute_sql(conn, &format!("ALTER TABLE {} ADD COLUMN {} TEXT", table, HLC_COLUMN))?;
+        }
+
+        let key_match = pks.iter().map(|pk| format!("{pk} = NEW.{pk}")).collect::<Vec<_>>().join(" AND ");
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS _sync_hlc_{table} AFTER UPDATE ON {table}
+             WHEN NEW.{hlc} IS OLD.{hlc} AND NEW.{hlc} IS NOT NULL
+             BEGIN
+                 UPDATE {table} SET {hlc} = NULL WHERE {key_match};
+             END;",
+            table = table,
+            hlc = HLC_COLUMN,
+            key_match = key_match,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Decide whether a remote version replaces the local one (last writer wins).
+/// Rows written by older clients have no HLC and fall back to comparing `updated_at`.
+fn remote_is_newer(local_hlc: Option<&Hlc>, local_updated_at: &SyncValue, remote_hlc: Option<&Hlc>, remote_updated_at: &SyncValue) -> bool {
+    match (local_hlc, remote_hlc) {
+        (Some(local), Some(remote)) => remote > local,
+        _ => local_updated_at.sqlite_cmp(remote_updated_at) != Ordering::Greater,
+    }
+}
+
+fn parse_hlc(value: &SyncValue) -> Option<Hlc> {
+    match value {
+        SyncValue::Text(s) => Hlc::parse(s),
+        _ => None,
+    }
+}
+
+/// Build a `pk1 = ? AND pk2 = ?` clause and its parameters from a JSON array of key values.
+fn pk_where_clause(pks: &[String], pk_json: &str) -> Result<(String, Row), SyncDbError> {
+    let values: Vec<Value> = serde_json::from_str(pk_json)
+        .map_err(|e| SyncDbError::Other { message: format!("Invalid key in delete log '{}': {}", pk_json, e) })?;
+    if values.len() != pks.len() {
+        return Err(SyncDbError::Other { message: format!("Key '{}' does not match primary key ({})", pk_json, pks.join(", ")) });
+    }
+
+    let clause = pks.iter().map(|pk| format!("{} = ?", pk)).collect::<Vec<_>>().join(" AND ");
+    Ok((clause, values.iter().map(SyncValue::from_json).collect()))
+}
+
+/// Bring the remote up to date: app migrations first, then the synced tables.
+async fn prepare_remote<S: SyncSchema>(ctx: &SyncContext<'_>, schema: &S) -> Result<(), SyncDbError> {
+    if let Some(migrations) = &ctx.options.remote_migrations {
+        migrate_remote(&ctx.remote, ctx.state, migrations).await?;
+    }
+    ensure_remote_schema(&ctx.remote, ctx.state, schema, ctx.options).await
+}
+
+/// Apply `migrations` to the remote, unless it was already migrated to their latest version.
+async fn migrate_remote(remote: &RemoteDb, state: &DbState, migrations: &Migrations) -> Result<(), SyncDbError> {
+    let migrated = format!("{}#{}", remote.base_url(), migrations.latest_version());
+    if meta_value(state, REMOTE_MIGRATIONS_KEY).await?.as_deref() == Some(migrated.as_str()) {
+        return Ok(());
+    }
+    apply_remote_migrations(remote, migrations).await?;
+    set_meta_value(state, REMOTE_MIGRATIONS_KEY, &migrated).await
+}
+
+async fn meta_value(state: &DbState, key: &str) -> Result<Option<String>, SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    Ok(conn.query_row("SELECT value FROM _sync_meta WHERE key = ?1", [key], |r| r.get(0)).optional()?)
+}
+
+async fn set_meta_value(state: &DbState, key: &str, value: &str) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    conn.execute("INSERT OR REPLACE INTO _sync_meta (key, value) VALUES (?1, ?2)", params![key, value])?;
+    Ok(())
+}
+
+/// Drop the cached verification of the remote, so the next check queries it again.
+async fn forget_remote_schema(state: &DbState) -> Result<(), SyncDbError> {
+    let conn_guard = state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    conn.execute("DELETE FROM _sync_meta WHERE key IN (?1, ?2)", params![SCHEMA_HASH_KEY, REMOTE_MIGRATIONS_KEY])?;
     Ok(())
 }
 
+/// Whether a remote statement failed on a table or column the remote doesn't have.
+/// Only remote statement errors carry their SQL.
+fn is_missing_remote_schema(error: &SyncDbError) -> bool {
+    match error {
+        SyncDbError::Sql { message, sql: Some(_), .. } => {
+            message.contains("no such table") || message.contains("no such column")
+        }
+        _ => false,
+    }
+}
+
+/// Create missing remote tables, columns and indexes, failing on incompatible differences.
+/// Skipped when neither the local schema nor the remote URL changed since the last verification.
 async fn ensure_remote_schema<S: SyncSchema>(
-    client: &reqwest::Client,
+    remote: &RemoteDb,
     state: &DbState,
-    schema: &S, 
-    url: &str, 
-    token: &str
-) -> Result<(), String> {
-    eprintln!("[{}] Verifying remote schema...", chrono::Local::now().format("%H:%M:%S%.3f"));
-    
-    let mut tasks = Vec::new();
-    let tables = schema.tables();
+    schema: &S,
+    options: &SyncOptions,
+) -> Result<(), SyncDbError> {
+    let delete_log = options.capture_deletes || options.change_tracking == ChangeTracking::Changelog;
 
-    // NEW: Ensure tables exist by running their CREATE statement from sqlite_master
-    let mut create_sqls = Vec::new();
-    {
-        let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-        let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
-        
-        for table_name in &tables {
-             let query = "SELECT sql FROM sqlite_master WHERE type='table' AND name=?";
-             let create_sql: Option<String> = conn.query_row(query, [table_name], |row| row.get(0)).ok();
-             
-             if let Some(sql) = create_sql {
-                 create_sqls.push((table_name.to_string(), sql));
-             }
+    let (local, hash) = {
+        let conn_guard = state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        let local = read_local_tables(conn, &schema.tables())?;
+        let hash = schema_hash(remote.base_url(), &local, delete_log);
+
+        let cached: Option<String> = conn
+            .query_row("SELECT value FROM _sync_meta WHERE key = ?1", [SCHEMA_HASH_KEY], |r| r.get(0))
+            .optional()?;
+        if cached.as_deref() == Some(hash.as_str()) {
+            eprintln!("Remote schema unchanged since last verification");
+            return Ok(());
         }
-    } // conn_guard dropped here, so &Connection is not held across await
+        (local, hash)
+    }; // conn_guard dropped here, so &Connection is not held across await
 
-    for (table_name, sql) in create_sqls {
-         eprintln!("Ensuring table {} exists on remote...", table_name);
-         let _ = execute_remote_query(client, url, token, &sql).await;
-    }
+    eprintln!("[{}] Verifying remote schema...", chrono::Local::now().format("%H:%M:%S%.3f"));
 
-    // Check standard columns for ALL tables in the schema
-    for table_name in tables {
-        let table = table_name.to_string();
-        
-        // Define columns to ensure existence of
-        let cols_to_check = vec!["updated_at", "created_at", "deleted_at"];
-        
-        for col_name in cols_to_check {
-            // Only check if local schema has this column
-            if let Some(col_type) = schema.get_column_type(&table, col_name) {
-                let url = url.to_string();
-                let token = token.to_string();
-                let table = table.clone();
-                let col = col_name.to_string();
-                let client = client.clone();
-                
-                tasks.push(tokio::spawn(async move {
-                    let default_val = if col_type.to_uppercase().contains("INT") {
-                        "0"
-                    } else {
-                        "'1970-01-01T00:00:00'"
-                    };
-                    
-                    let sql = format!("ALTER TABLE {} ADD COLUMN {} {} DEFAULT {}", 
-                        table, col, col_type, default_val);
-                    
-                    // Ignore error (will fail if column exists)
-                    let _ = execute_remote_query(&client, &url, &token, &sql).await;
-                }));
-            }
+    let remote_tables = remote_columns(options.retry.run(|| remote.query(REMOTE_COLUMNS_SQL.into())).await?);
+    let remote_indexes: HashSet<String> = options.retry.run(|| remote.query(REMOTE_INDEXES_SQL.into())).await?
+        .into_iter()
+        .filter_map(|row| match row.into_iter().next() {
+            Some(SyncValue::Text(name)) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    let mut statements = plan_migration(&local, &remote_tables, &remote_indexes)
+        .map_err(|errors| SyncDbError::Schema { message: errors.join("; ") })?;
+    if delete_log {
+        statements.push(DELETE_LOG_DDL.to_string());
+        let has_hlc = remote_tables.get("_sync_deletes")
+            .is_some_and(|cols| cols.iter().any(|c| c.name == HLC_COLUMN));
+        if !has_hlc {
+            statements.push(DELETE_LOG_HLC_DDL.to_string());
         }
     }
-    
-    for task in tasks {
-        let _ = task.await;
+
+    for sql in &statements {
+        eprintln!("Migrating remote schema: {}", sql);
     }
-    
+    remote.execute_transaction(statements.into_iter().map(RemoteStatement::from).collect()).await
+        .map_err(|e| {
+            eprintln!("Remote schema migration failed: {}", e);
+            SyncDbError::from(e)
+        })?;
+
+    set_meta_value(state, SCHEMA_HASH_KEY, &hash).await?;
+
     eprintln!("[{}] Remote schema verification finished.", chrono::Local::now().format("%H:%M:%S%.3f"));
     Ok(())
 }
 
 async fn sync_table(
-    client: &reqwest::Client, 
-    state: &DbState, 
-    url: &str, 
-    token: &str, 
-    table: &str,
-    columns: &[String],
-    pks: &[String],
-    updated_at_type: &str
-) -> Result<(), String> {
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    report: &mut TableReport,
+) -> Result<(), SyncDbError> {
+    let table = spec.name.as_str();
+    let updated_at_type = spec.updated_at_type.as_str();
+    let profile = ctx.options.profile.as_str();
     eprintln!("Syncing table: {}", table);
 
-    // Capture time AT START of sync
-    let now = if updated_at_type.to_uppercase().contains("INT") {
-         chrono::Local::now().timestamp_millis().to_string()
-    } else {
-         chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+    let (push_watermark, pull_watermark, pull_hlc) = {
+        let conn_guard = ctx.state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        (
+            read_watermark(conn, profile, table, "push_watermark", updated_at_type)?,
+            read_watermark(conn, profile, table, "pull_watermark", updated_at_type)?,
+            read_pull_hlc(conn, profile, table)?,
+        )
     };
-    
-    let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-    let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
-    
-    // Get last sync time
-    let mut last_sync_time = if updated_at_type.to_uppercase().contains("INT") {
-        "-1".to_string()
-    } else {
-        "1970-01-01 00:00:00".to_string()
+    eprintln!("Watermarks for {}: push {}, pull {} (HLC '{}')", table, push_watermark, pull_watermark, pull_hlc);
+
+    // 1. Collect local changes, stamped after everything already on the remote
+    observe_remote_clock(ctx, spec).await?;
+    let mut local = collect_local_changes(ctx, spec).await?;
+
+    // 2. PULL page by page, resolving rows changed on both sides as they arrive
+    let mut cursor = {
+        let conn_guard = ctx.state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        // A cursor of another layout, saved by an older version, restarts the pull
+        read_pull_cursor(conn, profile, table)?.filter(|c| c.len() == spec.pks.len() + 2)
     };
-    {
-        let query = format!("SELECT last_sync_time FROM sync_status WHERE table_name = '{}'", table);
-        if let Ok(rows) = query_strings(conn, &query) {
-            if let Some(row) = rows.first() {
-                 if let Some(Some(val)) = row.get(0) {
-                     last_sync_time = val.clone();
-                 }
-            }
-        }
+    if cursor.is_some() {
+        eprintln!("Resuming interrupted pull of {}", table);
     }
-    
-    // Fix: If we expect INT (millis) but got a Date String (from previous syncs), convert it.
-    if updated_at_type.to_uppercase().contains("INT") {
-        if let Err(_) = last_sync_time.parse::<i64>() {
-            // Not a number, try parsing as date
-            if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&last_sync_time, "%Y-%m-%d %H:%M:%S") {
-                last_sync_time = dt.and_utc().timestamp_millis().to_string();
-                eprintln!("Converting legacy date string '{}' to millis '{}' for table {}", dt, last_sync_time, table);
-            } else {
-                // If it fails, maybe it's just garbage or empty? Default to -1 is safer to ensure we catch 0s.
-                 eprintln!("Warning: Could not parse last_sync_time '{}' as int or date for table {}. Defaulting to -1.", last_sync_time, table);
-                 last_sync_time = "-1".to_string();
-            }
+    let pull_from = overlap_start(&pull_hlc, ctx.options.pull_overlap);
+    let mut forced_push = HashSet::new();
+    let mut pulled_hlc = None;
+    let mut pulled_max = None;
+    ctx.progress(SyncPhase::Pull, Some(spec), 0);
+    loop {
+        ctx.check_cancelled()?;
+        let mut page = fetch_remote_page(ctx, spec, &pull_watermark, &pull_from, cursor.as_ref()).await?;
+        // Watermarks only advance to values actually seen. The updated_at watermark selects
+        // rows without an HLC, so only theirs count.
+        let hlc_idx = spec.hlc_idx();
+        pulled_hlc = max_value(&page.rows, Some(hlc_idx)).into_iter().chain(pulled_hlc).max_by(|a, b| a.sqlite_cmp(b));
+        let unstamped = page.rows.iter().filter(|r| r[hlc_idx].is_null());
+        pulled_max = max_value(unstamped, spec.updated_at_idx()).into_iter().chain(pulled_max).max_by(|a, b| a.sqlite_cmp(b));
+        drop_seen_rows(ctx, spec, &mut page.rows).await?;
+        let forced = resolve_conflicts(ctx, spec, &mut local, &mut page.rows, report).await?;
+        apply_remote_rows(ctx, spec, page.rows, &forced, report).await?;
+        forced_push.extend(forced.push);
+        ctx.progress(SyncPhase::Pull, Some(spec), report.pulled);
+
+        if page.last {
+            break;
         }
+        if let Some(next) = &page.cursor {
+            let conn_guard = ctx.state.get_connection().await?;
+            let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+            save_pull_cursor(conn, profile, table, next)?;
+        }
+        cursor = page.cursor;
     }
+    let pushed_max = max_value(&local.rows, spec.updated_at_idx());
     
-    eprintln!("Last sync time for {}: {}", table, last_sync_time);
-    
-    // 1. PUSH
-    drop(conn_guard); 
+    // 3. PUSH rows and deletes in one remote transaction, so a failure leaves the remote untouched.
+    // Past this point the table runs to completion, so its watermarks match what was sent.
+    ctx.check_cancelled()?;
+    ctx.progress(SyncPhase::Push, Some(spec), 0);
+    let mut statements = upsert_statements(spec, &local.rows, &forced_push);
+    let pushed_deletes = if spec.log_deletes { delete_statements(ctx, spec, &mut statements).await? } else { Vec::new() };
+    ctx.remote_batch(&statements).await?;
+    report.pushed = local.rows.len();
+    ctx.progress(SyncPhase::Push, Some(spec), report.pushed);
+    mark_rows_pushed(ctx, spec, &local.rows).await?;
+    report.deletes_pushed = mark_deletes_pushed(ctx, spec, pushed_deletes).await?;
     
-    push_changes(client, state, url, token, table, columns, pks, &last_sync_time, updated_at_type).await?;
-    
-    // 2. PULL
-    pull_changes(client, state, url, token, table, columns, pks, &last_sync_time, updated_at_type).await?;
+    // 4. Replay remote deletes
+    if spec.log_deletes {
+        report.deletes_pulled = pull_deletes(ctx, spec, &pull_watermark, &pull_from).await?;
+    }
+
+    // 5. Purge expired tombstones
+    if let Some(retention) = ctx.options.tombstone_retention {
+        purge_tombstones(ctx, spec, retention).await?;
+    }
     
-    // 3. Update sync status
-    let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-    let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
+    // 6. Update sync status
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
     
-    let sql = format!(
-        "INSERT OR REPLACE INTO sync_status (table_name, last_sync_time, last_sync_direction, sync_count) 
-         VALUES ('{}', '{}', 'both', COALESCE((SELECT sync_count FROM sync_status WHERE table_name = '{}') + 1, 1))", 
-        table, now, table
-    );
-    execute_sql(conn, &sql).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO sync_status (profile, table_name, push_watermark, pull_watermark, pull_hlc, last_sync_direction, sync_count, last_synced_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, 'both', 1, datetime('now'))
+         ON CONFLICT(profile, table_name) DO UPDATE SET
+             push_watermark = excluded.push_watermark,
+             pull_watermark = excluded.pull_watermark,
+             pull_hlc = excluded.pull_hlc,
+             pull_cursor = NULL,
+             last_sync_direction = excluded.last_sync_direction,
+             sync_count = sync_count + 1,
+             last_synced_at = excluded.last_synced_at",
+        params![
+            profile,
+            table,
+            advance_watermark(&push_watermark, pushed_max, updated_at_type),
+            advance_watermark(&pull_watermark, pulled_max, updated_at_type),
+            advance_watermark(&pull_hlc, pulled_hlc, "TEXT"),
+        ],
+    )?;
     
     Ok(())
 }
 
-async fn push_changes(
-    client: &reqwest::Client, 
-    state: &DbState, 
-    url: &str, 
-    token: &str, 
-    table: &str, 
-    columns: &[String],
-    pks: &[String], 
-    last_sync_time: &str,
-    updated_at_type: &str
-) -> Result<(), String> {
-    let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-    let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
+/// Largest non-NULL value of a column, in SQLite order.
+fn max_value<'a>(rows: impl IntoIterator<Item = &'a Row>, idx: Option<usize>) -> Option<SyncValue> {
+    let idx = idx?;
+    rows.into_iter()
+        .map(|r| &r[idx])
+        .filter(|v| !v.is_null())
+        .max_by(|a, b| a.sqlite_cmp(b))
+        .cloned()
+}
 
-    if columns.is_empty() {
-        return Ok(());
+/// The later of the current watermark and the largest value observed in this sync.
+fn advance_watermark(current: &str, observed: Option<SyncValue>, updated_at_type: &str) -> SyncValue {
+    let current = time_value(current, updated_at_type);
+    match observed {
+        Some(v) if v.sqlite_cmp(&current) == Ordering::Greater => v,
+        _ => current,
     }
-    
-    let col_list = columns.join(", ");
-    
-    let query = if updated_at_type.to_uppercase().contains("INT") {
-        format!("SELECT {} FROM {} WHERE updated_at > {}", col_list, table, last_sync_time)
+}
+
+/// Read a watermark from `sync_status`, in the format of the table's `updated_at` column.
+/// Defaults to the epoch when the table was never synced.
+fn read_watermark(conn: &Connection, profile: &str, table: &str, column: &str, updated_at_type: &str) -> Result<String, SyncDbError> {
+    let mut watermark = if is_int_type(updated_at_type) {
+        "-1".to_string()
     } else {
-        format!("SELECT {} FROM {} WHERE updated_at > '{}'", col_list, table, last_sync_time)
+        "1970-01-01 00:00:00".to_string()
     };
-    
-    let rows = query_strings(conn, &query).map_err(|e| e.to_string())?;
+    let query = format!("SELECT {} FROM sync_status WHERE profile = ?1 AND table_name = ?2", column);
+    let stored = conn.query_row(&query, [profile, table], |row| row.get::<_, SyncValue>(0))
+        .optional()?;
+    if let Some(val) = stored {
+        if !val.is_null() {
+            watermark = val.to_string();
+        }
+    }
 
-    drop(conn_guard);
-    
-    if rows.is_empty() {
-        return Ok(());
+    // Fix: If we expect INT (millis) but got a Date String (from previous syncs), convert it.
+    if is_int_type(updated_at_type) && watermark.parse::<i64>().is_err() {
+        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&watermark, "%Y-%m-%d %H:%M:%S") {
+            watermark = dt.and_utc().timestamp_millis().to_string();
+            eprintln!("Converting legacy date string '{}' to millis '{}' for table {}", dt, watermark, table);
+        } else {
+            // If it fails, maybe it's just garbage or empty? Default to -1 is safer to ensure we catch 0s.
+            eprintln!("Warning: Could not parse {} '{}' as int or date for table {}. Defaulting to -1.", column, watermark, table);
+            watermark = "-1".to_string();
+        }
     }
 
-    // Capture IDs for logging
-    let id_col_idx = columns.iter().position(|c| c == "id");
-    let ids: Vec<String> = if let Some(idx) = id_col_idx {
-        rows.iter()
-            .filter_map(|r| r.get(idx).and_then(|v| v.clone()))
-            .collect()
-    } else {
-        Vec::new()
-    };
+    Ok(watermark)
+}
 
-    eprintln!("Pushing {} records for table {} (IDs: {:?})", rows.len(), table, ids);
+/// Whether `_sync_pushed` records a version as on the profile's remote. Parameters: profile,
+/// table name, primary key JSON, HLC.
+const SEEN_VERSION_SQL: &str = "SELECT 1 FROM _sync_pushed WHERE profile = ?1 AND table_name = ?2 AND pk = ?3 AND sync_hlc = ?4";
 
-    let mut statements = Vec::new();
-    
-    let update_set = columns.iter()
-        .map(|c| format!("{} = excluded.{}", c, c))
-        .collect::<Vec<_>>()
-        .join(", ");
+/// Lower bound of the HLCs to pull: `overlap` before `pull_hlc`, the newest pulled so far.
+fn overlap_start(pull_hlc: &str, overlap: chrono::Duration) -> String {
+    match Hlc::parse(pull_hlc) {
+        Some(hlc) => {
+            let millis = (hlc.millis - overlap.num_milliseconds()).max(0);
+            Hlc { millis, counter: 0, device_id: String::new() }.to_string()
+        }
+        None => pull_hlc.to_string(),
+    }
+}
 
-    for row in rows {
-        let mut values = Vec::new();
-        for val_opt in row {
-            match val_opt {
-                Some(v) => values.push(format!("'{}'", v.replace("'", "''"))),
-                None => values.push("NULL".to_string()),
-            }
+/// Drop remote rows whose version is recorded as applied from the profile's remote, fetched
+/// again by the pull overlap.
+async fn drop_seen_rows(ctx: &SyncContext<'_>, spec: &TableSpec, rows: &mut Vec<Row>) -> Result<(), SyncDbError> {
+    let hlc_idx = spec.hlc_idx();
+    if rows.iter().all(|r| r[hlc_idx].is_null()) {
+        return Ok(());
+    }
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    let mut seen_stmt = conn.prepare(SEEN_VERSION_SQL)?;
+    let mut fresh = Vec::with_capacity(rows.len());
+    for row in rows.drain(..) {
+        let seen = !row[hlc_idx].is_null() && seen_stmt.exists(params![
+            ctx.options.profile, spec.name, spec.row_key(&row), row[hlc_idx]
+        ])?;
+        if !seen {
+            fresh.push(row);
         }
-        
-        let sql = format!(
-            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT({}) DO UPDATE SET {} WHERE excluded.updated_at > {}.updated_at",
-            table,
-            col_list,
-            values.join(", "),
-            pks.join(", "),
-            update_set,
-            table
-        );
-        statements.push(sql);
     }
-    
-    execute_remote_batch(client, url, token, statements).await?;
-    
+    *rows = fresh;
     Ok(())
 }
 
-async fn pull_changes(
-    client: &reqwest::Client, 
-    state: &DbState, 
-    url: &str, 
-    token: &str, 
-    table: &str, 
-    columns: &[String],
-    pks: &[String],
-    last_sync_time: &str,
-    updated_at_type: &str
-) -> Result<(), String> {
-    let col_list = columns.join(", ");
-    
-    let sql = if updated_at_type.to_uppercase().contains("INT") {
-         format!("SELECT {} FROM {} WHERE updated_at > {}", col_list, table, last_sync_time)
-    } else {
-         format!("SELECT {} FROM {} WHERE updated_at > '{}'", col_list, table, last_sync_time)
-    };
-    
-    let rows = fetch_remote_rows(client, url, token, &sql).await?;
-    
-    if rows.is_empty() {
+/// Highest remote HLC pulled for `table`; empty when none was, which sorts before every HLC.
+fn read_pull_hlc(conn: &Connection, profile: &str, table: &str) -> Result<String, SyncDbError> {
+    let stored: Option<Option<String>> = conn
+        .query_row("SELECT pull_hlc FROM sync_status WHERE profile = ?1 AND table_name = ?2", [profile, table], |r| r.get(0))
+        .optional()?;
+    Ok(stored.flatten().unwrap_or_default())
+}
+
+/// Merge the newest HLC on the remote table into the clock. Rows stamped afterwards order
+/// after everything other devices have pulled, even when they were written offline with an
+/// old `updated_at`.
+async fn observe_remote_clock(ctx: &SyncContext<'_>, spec: &TableSpec) -> Result<(), SyncDbError> {
+    if spec.columns.is_empty() {
         return Ok(());
     }
-    
-    // Capture IDs for logging
-    let id_col_idx = columns.iter().position(|c| c == "id");
-    let ids: Vec<String> = if let Some(idx) = id_col_idx {
+    let stmt = RemoteStatement::new(format!("SELECT MAX({}) FROM {}", HLC_COLUMN, spec.name), Vec::new());
+    let newest = ctx.remote_rows(stmt).await?.into_iter().next().and_then(|row| row.into_iter().next());
+    if let Some(hlc) = newest.as_ref().and_then(parse_hlc) {
+        ctx.clock.observe(&hlc);
+    }
+    Ok(())
+}
+
+/// Select rows whose version the profile's remote doesn't have, stamping the ones written by
+/// the app since their last sync with a fresh HLC.
+///
+/// Rows pushed to or pulled from the profile's remote are recorded in `_sync_pushed`, so a change
+/// synced with one profile is still pushed to the others, and pulled rows are not pushed back.
+async fn collect_local_changes(ctx: &SyncContext<'_>, spec: &TableSpec) -> Result<LocalChanges, SyncDbError> {
+    let table = spec.name.as_str();
+    let columns = &spec.columns;
+    let mut changes = LocalChanges { rows: Vec::new() };
+
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+    if columns.is_empty() {
+        return Ok(changes);
+    }
+    spec.check_pks()?;
+    
+    let where_clause = spec.pks.iter().map(|pk| format!("{} = ?", pk)).collect::<Vec<_>>().join(" AND ");
+
+    if spec.use_changelog {
+        drain_changelog(conn, spec, &where_clause)?;
+    }
+
+    // Rows with a NULL HLC were written by the app since their last sync
+    let query = format!(
+        "SELECT {columns} FROM {table} LEFT JOIN _sync_pushed p ON p.profile = ? AND p.table_name = ? AND p.pk = {pk}
+         WHERE {table}.{hlc} IS NULL OR p.sync_hlc IS NOT {table}.{hlc}",
+        columns = columns.iter().map(|c| format!("{}.{}", table, c)).collect::<Vec<_>>().join(", "),
+        table = table,
+        pk = pk_json_sql(&spec.pks, table),
+        hlc = HLC_COLUMN,
+    );
+    let params = [SyncValue::Text(ctx.options.profile.clone()), SyncValue::Text(table.to_string())];
+    changes.rows = query_rows(conn, &query, &params)?;
+
+    // Stamp rows changed locally since their last sync with a fresh HLC
+    let hlc_idx = spec.hlc_idx();
+    if changes.rows.iter().any(|r| r[hlc_idx].is_null()) {
+        let mark = changelog_mark(ctx, conn)?;
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stamp_stmt = tx.prepare(&format!("UPDATE {} SET {} = ? WHERE {}", table, HLC_COLUMN, where_clause))?;
+            for row in changes.rows.iter_mut().filter(|r| r[hlc_idx].is_null()) {
+                let hlc = SyncValue::Text(ctx.clock.now().to_string());
+                let params = std::iter::once(&hlc).chain(spec.pk_idx.iter().map(|&i| &row[i]));
+                stamp_stmt.execute(params_from_iter(params))?;
+                row[hlc_idx] = hlc;
+            }
+        }
+        discard_own_changes(&tx, mark)?;
+        ctx.clock.persist(&tx)?;
+        tx.commit()?;
+    }
+
+    Ok(changes)
+}
+
+/// Turn the table's `_sync_changelog` entries into state every profile reads: rows written
+/// since carry a NULL HLC already, rows gone since become `_sync_deletes` entries.
+fn drain_changelog(conn: &Connection, spec: &TableSpec, where_clause: &str) -> Result<(), SyncDbError> {
+    let table = spec.name.as_str();
+    let mark: Option<i64> = conn.query_row(
+        "SELECT MAX(seq) FROM _sync_changelog WHERE table_name = ?1", [table], |r| r.get(0)
+    )?;
+    let Some(mark) = mark else {
+        return Ok(());
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    let entries = query_rows(
+        &tx,
+        "SELECT pk, MAX(changed_at) FROM _sync_changelog WHERE table_name = ? AND seq <= ? GROUP BY pk",
+        &[SyncValue::Text(table.to_string()), SyncValue::Integer(mark)],
+    )?;
+    let exists = format!("SELECT 1 FROM {} WHERE {}", table, where_clause);
+    for entry in entries {
+        let [SyncValue::Text(pk), changed_at] = &entry[..] else {
+            continue;
+        };
+        let (_, key) = pk_where_clause(&spec.pks, pk)?;
+        // Gone since it was logged: propagate as a delete
+        if query_rows(&tx, &exists, &key)?.is_empty() {
+            tx.execute(
+                "INSERT OR REPLACE INTO _sync_deletes (table_name, pk, deleted_at, pending) VALUES (?1, ?2, ?3, 1)",
+                params![table, pk, changed_at],
+            )?;
+        }
+    }
+    tx.execute("DELETE FROM _sync_changelog WHERE table_name = ?1 AND seq <= ?2", params![table, mark])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Statement recording in `_sync_pushed` that the profile's remote has the version of a row
+/// the table holds, unless the row changed since. Parameters: profile, table name, primary key
+/// values, HLC of the version.
+fn mark_row_sql(spec: &TableSpec) -> String {
+    format!(
+        "INSERT OR REPLACE INTO _sync_pushed (profile, table_name, pk, sync_hlc)
+         SELECT ?, ?, {pk}, {hlc} FROM {table} WHERE {where_clause} AND {hlc} = ?",
+        pk = pk_json_sql(&spec.pks, &spec.name),
+        hlc = HLC_COLUMN,
+        table = spec.name,
+        where_clause = spec.pks.iter().map(|pk| format!("{} = ?", pk)).collect::<Vec<_>>().join(" AND "),
+    )
+}
+
+fn mark_row_params(ctx: &SyncContext<'_>, spec: &TableSpec, row: &Row) -> Vec<SyncValue> {
+    [SyncValue::Text(ctx.options.profile.clone()), SyncValue::Text(spec.name.clone())].into_iter()
+        .chain(spec.pk_idx.iter().map(|&i| row[i].clone()))
+        .chain(std::iter::once(row[spec.hlc_idx()].clone()))
+        .collect()
+}
+
+/// Record the rows pushed once the remote transaction carrying them committed.
+async fn mark_rows_pushed(ctx: &SyncContext<'_>, spec: &TableSpec, rows: &[Row]) -> Result<(), SyncDbError> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut mark_stmt = tx.prepare(&mark_row_sql(spec))?;
+        for row in rows {
+            mark_stmt.execute(params_from_iter(mark_row_params(ctx, spec, row)))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Fetch the next page of rows changed on the remote since the last sync, starting after `cursor`.
+/// Rows pushed with an HLC are selected and paged by it; rows written by clients without HLCs
+/// by `updated_at`, after all the others.
+///
+/// Tables without an `updated_at` column can't be paged and are fetched in one request.
+async fn fetch_remote_page(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    watermark: &str,
+    pull_from: &str,
+    cursor: Option<&Row>,
+) -> Result<RemotePage, SyncDbError> {
+    let mut page = RemotePage { rows: Vec::new(), cursor: cursor.cloned(), last: true };
+    if spec.columns.is_empty() {
+        return Ok(page);
+    }
+    spec.check_pks()?;
+
+    // Inclusive, so rows sharing the watermark's timestamp are not lost; rows already
+    // applied are skipped by their HLC
+    let mut sql = format!(
+        "SELECT {} FROM {} WHERE ({hlc} > ? OR ({hlc} IS NULL AND {updated_at} >= ?))",
+        spec.columns.join(", "), spec.name, hlc = HLC_COLUMN, updated_at = UPDATED_AT_COLUMN
+    );
+    let mut params = vec![SyncValue::Text(pull_from.to_string()), time_value(watermark, &spec.updated_at_type)];
+
+    let updated_at_idx = spec.updated_at_idx();
+    let page_size = ctx.options.pull_page_size.max(1);
+    if updated_at_idx.is_some() {
+        let pks = spec.pks.join(", ");
+        let pk_params = vec!["?"; spec.pks.len()].join(", ");
+        match cursor.map(|c| c.split_first()) {
+            Some(Some((SyncValue::Null, rest))) => {
+                sql.push_str(&format!(" AND {} IS NULL AND ({}, {}) > (?, {})", HLC_COLUMN, UPDATED_AT_COLUMN, pks, pk_params));
+                params.extend(rest.iter().cloned());
+            }
+            Some(Some((hlc, rest))) => {
+                sql.push_str(&format!(" AND (({hlc}, {pks}) > (?, {pk_params}) OR {hlc} IS NULL)", hlc = HLC_COLUMN, pks = pks, pk_params = pk_params));
+                params.push(hlc.clone());
+                params.extend(rest.iter().skip(1).cloned());
+            }
+            _ => {}
+        }
+        sql.push_str(&format!(
+            " ORDER BY {hlc} IS NULL, {hlc}, CASE WHEN {hlc} IS NULL THEN {updated_at} END, {pks} LIMIT {limit}",
+            hlc = HLC_COLUMN, updated_at = UPDATED_AT_COLUMN, pks = pks, limit = page_size
+        ));
+    }
+
+    let mut rows = ctx.remote_rows(RemoteStatement::new(sql, params)).await?;
+
+    if let Some(idx) = updated_at_idx {
+        page.last = rows.len() < page_size;
+        if let Some(row) = rows.last().filter(|r| r.len() == spec.columns.len()) {
+            let key = [spec.hlc_idx(), idx].into_iter().chain(spec.pk_idx.iter().copied());
+            page.cursor = Some(key.map(|i| row[i].clone()).collect());
+        }
+    }
+
+    // Skip rows we can't key
+    rows.retain(|row| row.len() == spec.columns.len() && spec.pk_idx.iter().all(|&i| !row[i].is_null()));
+    page.rows = rows;
+    Ok(page)
+}
+
+fn read_pull_cursor(conn: &Connection, profile: &str, table: &str) -> Result<Option<Row>, SyncDbError> {
+    let cursor: Option<Option<String>> = conn
+        .query_row("SELECT pull_cursor FROM sync_status WHERE profile = ?1 AND table_name = ?2", [profile, table], |r| r.get(0))
+        .optional()?;
+    let Some(cursor) = cursor.flatten() else {
+        return Ok(None);
+    };
+    let values: Vec<Value> = serde_json::from_str(&cursor)
+        .map_err(|e| SyncDbError::Other { message: format!("Invalid pull cursor for {}: {}", table, e) })?;
+    Ok(Some(values.iter().map(SyncValue::from_json).collect()))
+}
+
+fn save_pull_cursor(conn: &Connection, profile: &str, table: &str, cursor: &Row) -> Result<(), SyncDbError> {
+    let json = Value::Array(cursor.iter().map(SyncValue::to_json).collect()).to_string();
+    conn.execute(
+        "INSERT INTO sync_status (profile, table_name, pull_cursor) VALUES (?1, ?2, ?3)
+         ON CONFLICT(profile, table_name) DO UPDATE SET pull_cursor = excluded.pull_cursor",
+        params![profile, table, json],
+    )?;
+    Ok(())
+}
+
+/// Resolve rows changed locally and by another device with the table's `ConflictResolver`.
+/// Updates both change sets in place and returns the keys whose chosen version must be forced.
+async fn resolve_conflicts(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    local: &mut LocalChanges,
+    remote: &mut Vec<Row>,
+    report: &mut TableReport,
+) -> Result<ForcedKeys, SyncDbError> {
+    let mut forced = ForcedKeys::default();
+    if local.rows.is_empty() || remote.is_empty() {
+        return Ok(forced);
+    }
+
+    let hlc_idx = spec.hlc_idx();
+    let updated_at_idx = spec.updated_at_idx();
+    let local_idx: HashMap<String, usize> = local.rows.iter().enumerate()
+        .map(|(i, row)| (spec.row_key(row), i))
+        .collect();
+
+    let mut drop_local = HashSet::new();
+    let mut drop_remote = HashSet::new();
+    let mut unresolved = Vec::new();
+
+    for remote_row in remote.iter_mut() {
+        let key = spec.row_key(remote_row);
+        let Some(&li) = local_idx.get(&key) else {
+            continue;
+        };
+        let remote_hlc = parse_hlc(&remote_row[hlc_idx]);
+        // Our own earlier push coming back is not a concurrent change
+        if remote_hlc.as_ref().is_some_and(|h| h.device_id == ctx.clock.device_id()) {
+            continue;
+        }
+
+        let local_row = &local.rows[li];
+        let updated_at = |row: &Row| updated_at_idx.map(|i| row[i].clone()).unwrap_or(SyncValue::Null);
+        let conflict = Conflict {
+            table: spec.name.clone(),
+            columns: spec.columns.clone(),
+            local: local_row.clone(),
+            remote: remote_row.clone(),
+            remote_is_newer: remote_is_newer(
+                parse_hlc(&local_row[hlc_idx]).as_ref(),
+                &updated_at(local_row),
+                remote_hlc.as_ref(),
+                &updated_at(remote_row),
+            ),
+        };
+        report.conflicts += 1;
+
+        match spec.resolver.resolve(&conflict) {
+            Resolution::Local => {
+                forced.push.insert(key.clone());
+                drop_remote.insert(key);
+            }
+            Resolution::Remote => {
+                forced.pull.insert(key.clone());
+                drop_local.insert(key);
+            }
+            Resolution::Merged(mut merged) => {
+                if merged.len() != spec.columns.len() {
+                    return Err(SyncDbError::Other {
+                        message: format!("Merged row for {} {} has {} values, expected {}", spec.name, key, merged.len(), spec.columns.len()),
+                    });
+                }
+                // The merged row is a new write that must order after both versions
+                for &i in &spec.pk_idx {
+                    merged[i] = local_row[i].clone();
+                }
+                if let Some(hlc) = &remote_hlc {
+                    ctx.clock.observe(hlc);
+                }
+                merged[hlc_idx] = SyncValue::Text(ctx.clock.now().to_string());
+
+                local.rows[li] = merged.clone();
+                *remote_row = merged;
+                forced.push.insert(key.clone());
+                forced.pull.insert(key);
+            }
+            Resolution::Unresolved => {
+                drop_local.insert(key.clone());
+                drop_remote.insert(key.clone());
+                unresolved.push((conflict, key));
+            }
+        }
+    }
+
+    local.rows.retain(|row| !drop_local.contains(&spec.row_key(row)));
+    remote.retain(|row| !drop_remote.contains(&spec.row_key(row)));
+
+    if !unresolved.is_empty() {
+        eprintln!("Recording {} unresolved conflicts for table {}", unresolved.len(), spec.name);
+        let conn_guard = ctx.state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        for (conflict, key) in &unresolved {
+            record_conflict(conn, conflict, key)?;
+        }
+        report.unresolved_conflicts = unresolved.len();
+    }
+
+    Ok(forced)
+}
+
+/// Upserts pushing `rows`, guarded so they never overwrite a newer remote version nor re-create
+/// a row deleted after they were written. Applying them twice is harmless, which makes the push
+/// safe to retry.
+fn upsert_statements(spec: &TableSpec, rows: &[Row], forced: &HashSet<String>) -> Vec<RemoteStatement> {
+    let table = spec.name.as_str();
+    let columns = &spec.columns;
+    
+    if rows.is_empty() {
+        return Vec::new();
+    }
+
+    // Capture IDs for logging
+    let id_col_idx = columns.iter().position(|c| c == "id");
+    let ids: Vec<String> = if let Some(idx) = id_col_idx {
         rows.iter()
-            .filter_map(|r| r.get(idx).and_then(|v| v.clone()))
+            .filter_map(|r| r.get(idx).map(SyncValue::to_string))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    eprintln!("Pushing {} records for table {} (IDs: {:?})", rows.len(), table, ids);
+
+    let update_set = columns.iter()
+        .map(|c| format!("{} = excluded.{}", c, c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let values = if spec.log_deletes {
+        format!(
+            "SELECT {} WHERE NOT EXISTS (SELECT 1 FROM _sync_deletes WHERE table_name = ? AND pk = ? AND deleted_at >= ?)",
+            placeholders
+        )
+    } else {
+        format!("VALUES ({})", placeholders)
+    };
+    let upsert = format!(
+        "INSERT INTO {} ({}) {} ON CONFLICT({}) DO UPDATE SET {}",
+        table,
+        columns.join(", "),
+        values,
+        spec.pks.join(", "),
+        update_set,
+    );
+
+    // Last writer wins by HLC; remote rows written by older clients compare by updated_at
+    let sql = format!(
+        "{upsert} WHERE excluded.{hlc} > {table}.{hlc} OR ({table}.{hlc} IS NULL AND excluded.{updated_at} >= {table}.{updated_at})",
+        upsert = upsert,
+        table = table,
+        hlc = HLC_COLUMN,
+        updated_at = UPDATED_AT_COLUMN,
+    );
+
+    let updated_at_idx = spec.updated_at_idx();
+    rows.iter()
+        .map(|row| {
+            let key = spec.row_key(row);
+            let sql = if forced.contains(&key) { upsert.clone() } else { sql.clone() };
+            let mut params = row.clone();
+            if spec.log_deletes {
+                let updated_at = updated_at_idx.map(|i| row[i].clone()).unwrap_or(SyncValue::Null);
+                params.extend([SyncValue::Text(table.to_string()), SyncValue::Text(key), updated_at]);
+            }
+            RemoteStatement::new(sql, params)
+        })
+        .collect()
+}
+
+/// Apply remote rows locally, keeping local versions with a newer HLC unless the key is forced.
+/// Rows are recorded as being on the remote only when their version came from it; merged rows
+/// are recorded once pushed.
+async fn apply_remote_rows(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    rows: Vec<Row>,
+    forced: &ForcedKeys,
+    report: &mut TableReport,
+) -> Result<(), SyncDbError> {
+    let table = spec.name.as_str();
+    let columns = &spec.columns;
+    let pks = &spec.pks;
+    
+    if rows.is_empty() {
+        return Ok(());
+    }
+    
+    // Capture IDs for logging
+    let id_col_idx = columns.iter().position(|c| c == "id");
+    let ids: Vec<String> = if let Some(idx) = id_col_idx {
+        rows.iter()
+            .filter_map(|r| r.get(idx).map(SyncValue::to_string))
             .collect()
     } else {
         Vec::new()
     };
     
     eprintln!("Pulling {} records for table {} (IDs: {:?})", rows.len(), table, ids);
+
+    let updated_at_idx = spec.updated_at_idx();
+    let hlc_idx = spec.hlc_idx();
+    let deleted_at_idx = columns.iter().position(|c| c == "deleted_at");
     
-    let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-    let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
     
     // Disable FKs for this connection to allow out-of-order insertion (e.g. self-referencing items)
-    conn.execute("PRAGMA foreign_keys = OFF", []).map_err(|e| e.to_string())?;
+    conn.execute("PRAGMA foreign_keys = OFF", [])?;
     
+    let mark = changelog_mark(ctx, conn)?;
     let mut collision_count = 0;
+    let mut tombstone_count = 0;
     
-    // Explicit scope for transaction to ensure it drops before we re-enable FKs later (if we wanted to)
-    // Actually rusqlite transaction borrow checker might be tricky.
-    // Let's use `unchecked_transaction`
-    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
-    
-    for row in rows {
-        let mut row_map = HashMap::new();
-        for (i, col) in columns.iter().enumerate() {
-            if let Some(val) = row.get(i).and_then(|v| v.clone()) {
-                 row_map.insert(col.to_string(), val);
-            }
-        }
-        
-        let mut pk_conditions = Vec::new();
-        for pk in pks {
-             let pk_val = row_map.get(pk).cloned().unwrap_or_default();
-             if pk_val.is_empty() { continue; } 
-             pk_conditions.push(format!("{} = '{}'", pk, pk_val.replace("'", "''")));
-        }
-        
-        if pk_conditions.len() != pks.len() {
-             // Skip if we couldn't find all PK values
-             continue; 
-        }
+    let tx = conn.unchecked_transaction()?;
 
-        let remote_updated_at = row_map.get("updated_at").cloned().unwrap_or_default();
-        
-        let mut should_update = true;
-        {
-            let where_clause = pk_conditions.join(" AND ");
-            let check_sql = format!("SELECT updated_at FROM {} WHERE {}", table, where_clause);
-            let mut stmt = tx.prepare(&check_sql).map_err(|e| e.to_string())?;
-            if let Ok(local_updated) = stmt.query_row([], |r| r.get::<_, String>(0)) {
-                if local_updated > remote_updated_at {
-                    should_update = false;
-                    collision_count += 1;
+    {
+        let where_clause = pks.iter().map(|pk| format!("{} = ?", pk)).collect::<Vec<_>>().join(" AND ");
+        let mut check_stmt = tx.prepare(&format!("SELECT {}, {} FROM {} WHERE {}", UPDATED_AT_COLUMN, HLC_COLUMN, table, where_clause))?;
+        let mut pending_delete_stmt = tx.prepare(
+            "SELECT d.deleted_at FROM _sync_deletes d WHERE d.table_name = ?1 AND d.pk = ?2 AND NOT EXISTS (
+                 SELECT 1 FROM _sync_pushed p
+                 WHERE p.profile = ?3 AND p.table_name = d.table_name AND p.pk = d.pk AND p.sync_hlc = d.sync_hlc
+             )"
+        )?;
+        let mut mark_stmt = tx.prepare(&mark_row_sql(spec))?;
+        let mut upsert_stmt = tx.prepare(&format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+            table,
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        ))?;
+
+        for row in rows {
+            let remote_hlc = parse_hlc(&row[hlc_idx]);
+
+            let key = spec.row_key(&row);
+            if !forced.pull.contains(&key) {
+                let remote_updated_at = updated_at_idx.map(|i| row[i].clone()).unwrap_or(SyncValue::Null);
+                let pk_values = spec.pk_idx.iter().map(|&i| &row[i]);
+                let local = check_stmt
+                    .query_row(params_from_iter(pk_values), |r| Ok((r.get::<_, SyncValue>(0)?, r.get::<_, SyncValue>(1)?)))
+                    .optional()?;
+
+                if let Some((local_updated_at, local_hlc)) = local {
+                    // Already applied
+                    if remote_hlc.is_some() && local_hlc == row[hlc_idx] {
+                        mark_stmt.execute(params_from_iter(mark_row_params(ctx, spec, &row)))?;
+                        continue;
+                    }
+                    if !remote_is_newer(parse_hlc(&local_hlc).as_ref(), &local_updated_at, remote_hlc.as_ref(), &remote_updated_at) {
+                        collision_count += 1;
+                        continue;
+                    }
+                } else if spec.log_deletes {
+                    // Deleted here but not pushed yet: don't resurrect a row the delete supersedes
+                    let deleted_at = pending_delete_stmt
+                        .query_row(params![table, key, ctx.options.profile], |r| r.get::<_, SyncValue>(0))
+                        .optional()?;
+                    if deleted_at.is_some_and(|d| remote_updated_at.sqlite_cmp(&d) != Ordering::Greater) {
+                        collision_count += 1;
+                        continue;
+                    }
                 }
             }
-        }
-        
-        if should_update {
-             let mut values = Vec::new();
-             for val_opt in row {
-                 match val_opt {
-                     Some(v) => values.push(format!("'{}'", v.replace("'", "''"))),
-                     None => values.push("NULL".to_string()),
-                 }
-             }
-             
-             let upsert_sql = format!(
-                "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
-                table,
-                col_list,
-                values.join(", ")
-            );
-            tx.execute(&upsert_sql, []).map_err(|e| e.to_string())?;
+
+            if let Some(hlc) = &remote_hlc {
+                ctx.clock.observe(hlc);
+            }
+
+            if deleted_at_idx.is_some_and(|i| !row[i].is_null()) {
+                tombstone_count += 1;
+            }
+
+            upsert_stmt.execute(params_from_iter(row.iter()))?;
+            if remote_hlc.is_some() && !forced.push.contains(&key) {
+                mark_stmt.execute(params_from_iter(mark_row_params(ctx, spec, &row)))?;
+            }
+            report.pulled += 1;
         }
     }
     
-    tx.commit().map_err(|e| e.to_string())?;
+    discard_own_changes(&tx, mark)?;
+    ctx.clock.persist(&tx)?;
+    tx.commit()?;
     
     // Re-enable FKs
-    conn.execute("PRAGMA foreign_keys = ON", []).map_err(|e| e.to_string())?;
+    conn.execute("PRAGMA foreign_keys = ON", [])?;
     
+    report.skipped_collisions += collision_count;
     if collision_count > 0 {
         eprintln!("Ignored {} remote updates due to newer local versions", collision_count);
     }
+    if tombstone_count > 0 {
+        eprintln!("Applied {} remote tombstones for table {}", tombstone_count, table);
+    }
     
     Ok(())
 }
 
-async fn fetch_remote_rows(client: &reqwest::Client, url: &str, token: &str, sql: &str) -> Result<Vec<Vec<Option<String>>>, String> {
-    let http_url = url.replace("libsql://", "https://");
-    // Only log URL once to avoid spamming, or log debug?
-    // Let's log it once per connection or just ensure user knows.
-    // For now, let's just log it if it changes or just simple print.
-    // actually, let's validation:
-    eprintln!("Connecting to remote DB: {}", http_url); 
-    
-    let response = client
-        .post(&http_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&json!({
-            "statements": [sql]
-        })).map_err(|e| e.to_string())?)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
-        
-    let text = response.text().await.map_err(|e| e.to_string())?;
-    
-    let results: Vec<TursoItemResponse> = serde_json::from_str(&text).map_err(|e| format!("Parse error: {} (Body: {})", e, text))?;
-    
-    if let Some(first) = results.first() {
-        match first {
-            TursoItemResponse::Error { error } => Err(error.message.clone()),
-            TursoItemResponse::Success { results } => {
-                let mut data = Vec::new();
-                for r in &results.rows {
-                   let mut row_vec = Vec::new();
-                   for cell in r {
-                       match cell {
-                           Value::Null => row_vec.push(None),
-                           Value::String(s) => row_vec.push(Some(s.clone())),
-                           Value::Number(n) => row_vec.push(Some(n.to_string())),
-                           Value::Bool(b) => row_vec.push(Some(b.to_string())),
-                           _ => row_vec.push(Some(cell.to_string())),
-                       }
-                   }
-                   data.push(row_vec);
-                }
-                Ok(data)
+/// Add statements sending hard deletes captured in `_sync_deletes` that the profile's remote
+/// doesn't have yet to the remote table and its delete log. New entries are stamped with an HLC
+/// first, so every remote records a delete with the same one. Returns the entries sent, to be
+/// marked pushed once the statements commit.
+async fn delete_statements(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    statements: &mut Vec<RemoteStatement>,
+) -> Result<Vec<(String, SyncValue)>, SyncDbError> {
+    let table = spec.name.as_str();
+
+    let pending = {
+        let conn_guard = ctx.state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+        let mut pending = query_rows(
+            conn,
+            "SELECT d.pk, d.deleted_at, d.sync_hlc FROM _sync_deletes d
+             LEFT JOIN _sync_pushed p ON p.profile = ? AND p.table_name = d.table_name AND p.pk = d.pk
+             WHERE d.table_name = ? AND (d.sync_hlc IS NULL OR p.sync_hlc IS NOT d.sync_hlc)",
+            &[SyncValue::Text(ctx.options.profile.clone()), SyncValue::Text(table.to_string())],
+        )?;
+        if pending.iter().any(|r| r[2].is_null()) {
+            let tx = conn.unchecked_transaction()?;
+            for row in pending.iter_mut().filter(|r| r[2].is_null()) {
+                let hlc = SyncValue::Text(ctx.clock.now().to_string());
+                tx.execute(
+                    "UPDATE _sync_deletes SET sync_hlc = ?1 WHERE table_name = ?2 AND pk = ?3 AND sync_hlc IS NULL",
+                    params![hlc, table, row[0]],
+                )?;
+                row[2] = hlc;
             }
+            ctx.clock.persist(&tx)?;
+            tx.commit()?;
         }
-    } else {
-        Ok(Vec::new())
+        pending
+    };
+
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    eprintln!("Pushing {} deletes for table {}", pending.len(), table);
+
+    let mut pushed = Vec::new();
+    for row in pending {
+        let [SyncValue::Text(pk), deleted_at, hlc] = &row[..] else {
+            continue;
+        };
+
+        // Only delete the remote row if it was not updated after our delete
+        let (mut where_clause, mut params) = pk_where_clause(&spec.pks, pk)?;
+        if let Some(clause) = spec.deleted_since_clause() {
+            where_clause = format!("{} AND {}", where_clause, clause);
+            params.push(deleted_at.clone());
+        }
+        statements.push(RemoteStatement::new(format!("DELETE FROM {} WHERE {}", table, where_clause), params));
+        statements.push(RemoteStatement::new(
+            "INSERT OR REPLACE INTO _sync_deletes (table_name, pk, deleted_at, pending, sync_hlc) VALUES (?, ?, ?, 0, ?)",
+            vec![SyncValue::Text(table.to_string()), SyncValue::Text(pk.clone()), deleted_at.clone(), hlc.clone()],
+        ));
+        pushed.push((pk.clone(), hlc.clone()));
     }
+
+    Ok(pushed)
 }
 
-pub async fn execute_remote_batch(client: &reqwest::Client, url: &str, token: &str, statements: Vec<String>) -> Result<(), String> {
-    let http_url = url.replace("libsql://", "https://");
-    
-    let response = client
-        .post(http_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&json!({
-            "statements": statements
-        })).map_err(|e| e.to_string())?)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
-        
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("Server error: {} - {}", status, body));
+/// Record the deletes pushed once the remote transaction carrying them committed.
+async fn mark_deletes_pushed(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    pushed: Vec<(String, SyncValue)>,
+) -> Result<usize, SyncDbError> {
+    let table = spec.name.as_str();
+    if pushed.is_empty() {
+        return Ok(0);
     }
-    
-    let text = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-        
-    match serde_json::from_str::<Vec<TursoItemResponse>>(&text) {
-        Ok(results) => {
-            for (i, result) in results.iter().enumerate() {
-                if let TursoItemResponse::Error { error } = result {
-                    eprintln!("[{}] Error in batch statement {}: {}", chrono::Local::now().format("%H:%M:%S%.3f"), i, error.message);
-                    return Err(format!("Batch statement {} failed: {}", i, error.message));
-                }
-            }
-        },
-        Err(e) => {
-            eprintln!("[{}] Warning: Failed to parse batch response: {} (Body: {})", chrono::Local::now().format("%H:%M:%S%.3f"), e, text);
-        }
+
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    let count = pushed.len();
+    for (pk, hlc) in pushed {
+        // Entries replaced by a newer delete while we were pushing stay pending
+        conn.execute(
+            "INSERT OR REPLACE INTO _sync_pushed (profile, table_name, pk, sync_hlc)
+             SELECT ?1, table_name, pk, sync_hlc FROM _sync_deletes WHERE table_name = ?2 AND pk = ?3 AND sync_hlc = ?4",
+            params![ctx.options.profile, table, pk, hlc],
+        )?;
+        conn.execute(
+            "UPDATE _sync_deletes SET pending = 0 WHERE table_name = ?1 AND pk = ?2 AND sync_hlc = ?3",
+            params![table, pk, hlc],
+        )?;
     }
-    
-    Ok(())
+
+    Ok(count)
 }
 
+/// Replay deletes recorded on the remote by other devices since the last sync, selected by
+/// HLC like rows are. Deletes already replayed are skipped.
+async fn pull_deletes(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    watermark: &str,
+    pull_from: &str,
+) -> Result<usize, SyncDbError> {
+    let table = spec.name.as_str();
+    let stmt = RemoteStatement::new(
+        "SELECT pk, deleted_at, sync_hlc FROM _sync_deletes
+         WHERE table_name = ? AND (sync_hlc > ? OR (sync_hlc IS NULL AND deleted_at > ?))",
+        vec![
+            SyncValue::Text(table.to_string()),
+            SyncValue::Text(pull_from.to_string()),
+            time_value(watermark, &spec.updated_at_type),
+        ],
+    );
+    let rows = ctx.remote_rows(stmt).await?;
 
-async fn execute_remote_query(client: &reqwest::Client, url: &str, token: &str, stmt: &str) -> Result<TursoResponseUnified, String> {
-    let http_url = url.replace("libsql://", "https://");
-    
-    let response = client
-        .post(http_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&json!({
-            "statements": [stmt]
-        })).map_err(|e| e.to_string())?)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
-        
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("Server error: {} - {}", status, body));
+    if rows.is_empty() {
+        return Ok(0);
     }
-    
-    let text = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-    
-    let raw_results: Vec<TursoItemResponse> = serde_json::from_str(&text)
-        .map_err(|e| format!("Failed to parse response: {} (Body: {})", e, text))?;
-        
-    let mut results = Vec::new();
-    for item in raw_results {
-        match item {
-            TursoItemResponse::Success { results: res } => {
-                results.push(TursoResult {
-                    rows: Some(res.rows),
-                    error: None,
-                });
-            },
-            TursoItemResponse::Error { error } => {
-                results.push(TursoResult {
-                    rows: None,
-                    error: Some(error.message),
-                });
+
+    eprintln!("Pulling {} deletes for table {}", rows.len(), table);
+
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    let mark = changelog_mark(ctx, conn)?;
+    let tx = conn.unchecked_transaction()?;
+    let mut count = 0;
+
+    for row in rows {
+        let [SyncValue::Text(pk), deleted_at, remote_hlc] = &row[..] else {
+            continue;
+        };
+        if tx.prepare_cached(SEEN_VERSION_SQL)?.exists(params![ctx.options.profile, table, pk, remote_hlc])? {
+            continue;
+        }
+        let hlc = match parse_hlc(remote_hlc) {
+            Some(hlc) => {
+                ctx.clock.observe(&hlc);
+                hlc
             }
+            None => ctx.clock.now(),
+        };
+
+        // Keep rows that were updated locally after the remote delete
+        let (mut where_clause, mut params) = pk_where_clause(&spec.pks, pk)?;
+        if let Some(clause) = spec.deleted_since_clause() {
+            where_clause = format!("{} AND {}", where_clause, clause);
+            params.push(deleted_at.clone());
         }
+        count += tx.execute(
+            &format!("DELETE FROM {} WHERE {}", table, where_clause),
+            params_from_iter(params.iter()),
+        )?;
+
+        // The delete trigger just logged this as a local delete; record it as already on this
+        // profile's remote, while other profiles still get it pushed
+        tx.execute(
+            "INSERT OR REPLACE INTO _sync_deletes (table_name, pk, deleted_at, pending, sync_hlc) VALUES (?1, ?2, ?3, 0, ?4)",
+            params![table, pk, deleted_at, hlc.to_string()],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO _sync_pushed (profile, table_name, pk, sync_hlc) VALUES (?1, ?2, ?3, ?4)",
+            params![ctx.options.profile, table, pk, hlc.to_string()],
+        )?;
     }
-        
-    Ok(TursoResponseUnified { results })
+
+    discard_own_changes(&tx, mark)?;
+    ctx.clock.persist(&tx)?;
+    tx.commit()?;
+    Ok(count)
+}
+
+/// Physically remove tombstones older than the retention horizon, locally and on the remote.
+async fn purge_tombstones(
+    ctx: &SyncContext<'_>,
+    spec: &TableSpec,
+    retention: chrono::Duration,
+) -> Result<(), SyncDbError> {
+    let table = spec.name.as_str();
+    let cutoff_time = chrono::Local::now() - retention;
+    let mut local = Vec::new();
+    let mut remote = Vec::new();
+
+    if let Some(deleted_at_type) = &spec.deleted_at_type {
+        let cutoff = time_value(&format_time(cutoff_time, deleted_at_type), deleted_at_type);
+        let stmt = RemoteStatement::new(
+            format!("DELETE FROM {} WHERE deleted_at IS NOT NULL AND deleted_at < ?", table),
+            vec![cutoff],
+        );
+        local.push(stmt.clone());
+        remote.push(stmt);
+    }
+
+    if spec.log_deletes {
+        let cutoff = time_value(&format_time(cutoff_time, &spec.updated_at_type), &spec.updated_at_type);
+        let table_name = SyncValue::Text(table.to_string());
+        // Only entries every profile synced so far has received
+        local.push(RemoteStatement::new(
+            "DELETE FROM _sync_deletes WHERE table_name = ?1 AND deleted_at < ?2 AND NOT EXISTS (
+                 SELECT 1 FROM sync_status s WHERE s.table_name = ?1 AND NOT EXISTS (
+                     SELECT 1 FROM _sync_pushed p WHERE p.profile = s.profile AND p.table_name = ?1
+                         AND p.pk = _sync_deletes.pk AND p.sync_hlc = _sync_deletes.sync_hlc
+                 )
+             )",
+            vec![table_name.clone(), cutoff.clone()],
+        ));
+        remote.push(RemoteStatement::new(
+            "DELETE FROM _sync_deletes WHERE table_name = ? AND deleted_at < ?",
+            vec![table_name, cutoff],
+        ));
+    }
+
+    if local.is_empty() {
+        return Ok(());
+    }
+    // Versions recorded for rows and deletes that are gone now
+    local.push(RemoteStatement::new(
+        format!(
+            "DELETE FROM _sync_pushed WHERE table_name = ?1
+             AND pk NOT IN (SELECT pk FROM _sync_deletes WHERE table_name = ?1)
+             AND pk NOT IN (SELECT {} FROM {})",
+            pk_json_sql(&spec.pks, table), table
+        ),
+        vec![SyncValue::Text(table.to_string())],
+    ));
+
+    ctx.remote_batch(&remote).await?;
+
+    let conn_guard = ctx.state.get_connection().await?;
+    let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+    let mark = changelog_mark(ctx, conn)?;
+    for stmt in local {
+        conn.execute(&stmt.sql, params_from_iter(stmt.params.iter()))?;
+    }
+    discard_own_changes(conn, mark)?;
+
+    Ok(())
 }
 
 /// Struct to hold dynamically loaded schema information
 pub struct DynamicSchema {
     tables: Vec<String>,
     table_info: HashMap<String, TableInfo>,
+    resolvers: HashMap<String, ConflictResolver>,
+    warnings: Vec<String>,
 }
 
 struct TableInfo {
     columns: Vec<String>,
     pks: Vec<String>,
     column_types: HashMap<String, String>,
+    /// Tables referenced through foreign keys.
+    parents: Vec<String>,
+}
+
+/// Glob patterns (`*` and `?`, case-insensitive) selecting tables for [`DynamicSchema::discover`].
+#[derive(Debug, Clone, Default)]
+pub struct TableFilter {
+    /// Tables must match one of these; empty includes every table.
+    pub include: Vec<String>,
+    /// Tables matching any of these are left out.
+    pub exclude: Vec<String>,
+}
+
+impl TableFilter {
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    pub fn matches(&self, table: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, table)))
+            && !self.exclude.iter().any(|p| glob_match(p, table))
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    // Position after the last `*` and the name position it was matched up to, for backtracking
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// User tables of the database matching `filter`, in creation order.
+/// SQLite internals, virtual tables and the library's metadata tables are left out.
+fn discover_tables(conn: &Connection, filter: &TableFilter) -> Result<Vec<String>, SyncDbError> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' AND sql NOT LIKE 'CREATE VIRTUAL%'
+         ORDER BY rowid"
+    )?;
+    let names = stmt.query_map([], |r| r.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(names.into_iter()
+        .filter(|name| !is_metadata_table(name) && filter.matches(name))
+        .collect())
+}
+
+fn read_table_info(conn: &Connection, table: &str) -> Result<TableInfo, SyncDbError> {
+    let mut columns = Vec::new();
+    let mut column_types = HashMap::new();
+
+    // pragma table_info returns: cid, name, type, notnull, dflt_value, pk
+    let query = format!("PRAGMA table_info({})", table);
+    let mut stmt = conn.prepare(&query)?;
+
+    struct ColumnMeta {
+        name: String,
+        col_type: String,
+        pk_idx: i32,
+    }
+
+    let column_iter = stmt.query_map([], |row| {
+        Ok(ColumnMeta {
+            name: row.get(1)?,
+            col_type: row.get(2)?,
+            pk_idx: row.get(5)?,
+        })
+    })?;
+
+    let mut pk_cols = Vec::new();
+
+    for col in column_iter {
+        let col = col?;
+        columns.push(col.name.clone());
+        column_types.insert(col.name.clone(), col.col_type.clone());
+
+        if col.pk_idx > 0 {
+            pk_cols.push(col);
+        }
+    }
+
+    // Sort PKs by index (composite keys order matters)
+    pk_cols.sort_by_key(|c| c.pk_idx);
+    let pks = pk_cols.into_iter().map(|c| c.name).collect();
+
+    // pragma foreign_key_list returns: id, seq, table, from, to, ...
+    let mut fk_stmt = conn.prepare(&format!("PRAGMA foreign_key_list({})", table))?;
+    let mut parents = fk_stmt.query_map([], |row| row.get::<_, String>(2))?
+        .collect::<Result<Vec<_>, _>>()?;
+    parents.dedup();
+
+    Ok(TableInfo { columns, pks, column_types, parents })
 }
 
 impl DynamicSchema {
-    /// Load schema from the database for the given list of tables.
-    /// If tables list is empty, it could potentially discover all tables (optional future feature),
-    /// but for now we expect a list of tables to include.
-    pub async fn load(state: &DbState, target_tables: Vec<&str>) -> Result<Self, String> {
-        let conn_guard = state.get_connection().await.map_err(|e| e.to_string())?;
-        let conn = conn_guard.as_ref().ok_or("Database not initialized")?;
-        
-        let mut schema = DynamicSchema {
-            tables: target_tables.iter().map(|s| s.to_string()).collect(),
+    fn new() -> Self {
+        DynamicSchema {
+            tables: Vec::new(),
             table_info: HashMap::new(),
-        };
+            resolvers: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
 
+    /// Load schema from the database for the given list of tables.
+    /// Tables without a declared primary key fall back to `id`, with a warning.
+    ///
+    /// Tables are reordered so that every table comes after the tables its foreign keys
+    /// reference; the given order is kept otherwise.
+    pub async fn load(state: &DbState, target_tables: Vec<&str>) -> Result<Self, SyncDbError> {
+        let conn_guard = state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
 
-        
-        // Refactored implementation below replacing the loop
+        let mut schema = DynamicSchema::new();
         for table in target_tables {
-            let mut columns = Vec::new();
-            let mut pks = Vec::new();
-            let mut column_types = HashMap::new();
-            
-            // pragma table_info returns: cid, name, type, notnull, dflt_value, pk
-            let query = format!("PRAGMA table_info({})", table);
-            let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
-            
-            struct ColumnMeta {
-                name: String,
-                col_type: String,
-                pk_idx: i32,
-            }
-            
-            let column_iter = stmt.query_map([], |row| {
-                Ok(ColumnMeta {
-                    name: row.get(1)?,
-                    col_type: row.get(2)?,
-                    pk_idx: row.get(5)?,
-                })
-            }).map_err(|e| e.to_string())?;
-
-            let mut pk_cols = Vec::new();
-
-            for col in column_iter {
-                let col = col.map_err(|e| e.to_string())?;
-                columns.push(col.name.clone());
-                column_types.insert(col.name.clone(), col.col_type.clone());
-                
-                if col.pk_idx > 0 {
-                    pk_cols.push(col);
-                }
-            }
-            
-            // Sort PKs by index (composite keys order matters)
-            pk_cols.sort_by_key(|c| c.pk_idx);
-            pks = pk_cols.into_iter().map(|c| c.name).collect();
-
-            if pks.is_empty() {
-                if columns.contains(&"id".to_string()) {
-                    pks.push("id".to_string());
-                }
+            let mut info = read_table_info(conn, table)?;
+            if info.pks.is_empty() && info.columns.iter().any(|c| c == "id") {
+                schema.warn(format!("Table {} has no primary key, using id", table));
+                info.pks.push("id".to_string());
             }
+            schema.add_table(table, info);
+        }
+        schema.order_by_dependencies();
 
-            schema.table_info.insert(table.to_string(), TableInfo { columns, pks, column_types });
+        Ok(schema)
+    }
+
+    /// Load every user table matching `filter`, ordered by foreign keys.
+    ///
+    /// Tables without a primary key or an `updated_at` column can't be synced; they are left
+    /// out and listed in [`warnings`](Self::warnings).
+    pub async fn discover(state: &DbState, filter: &TableFilter) -> Result<Self, SyncDbError> {
+        let conn_guard = state.get_connection().await?;
+        let conn = conn_guard.as_ref().ok_or(SyncDbError::NotInitialized)?;
+
+        let mut schema = DynamicSchema::new();
+        for table in discover_tables(conn, filter)? {
+            let info = read_table_info(conn, &table)?;
+            if info.pks.is_empty() {
+                schema.warn(format!("Skipping table {}: no primary key", table));
+            } else if !info.columns.iter().any(|c| c == UPDATED_AT_COLUMN) {
+                schema.warn(format!("Skipping table {}: no updated_at column", table));
+            } else {
+                schema.add_table(&table, info);
+            }
         }
-        
+        schema.order_by_dependencies();
+        eprintln!("Discovered {} tables to sync: {:?}", schema.tables.len(), schema.tables);
+
         Ok(schema)
     }
+
+    /// Problems found while loading the schema, e.g. tables left out by discovery.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn warn(&mut self, warning: String) {
+        eprintln!("Warning: {}", warning);
+        self.warnings.push(warning);
+    }
+
+    fn add_table(&mut self, table: &str, info: TableInfo) {
+        self.tables.push(table.to_string());
+        self.table_info.insert(table.to_string(), info);
+    }
+
+    fn order_by_dependencies(&mut self) {
+        let parents: HashMap<&str, Vec<&str>> = self.table_info.iter()
+            .map(|(t, info)| (t.as_str(), info.parents.iter().map(String::as_str).collect()))
+            .collect();
+        self.tables = dependency_order(&self.tables, &parents);
+    }
+}
+
+/// Order tables so parents come before the tables referencing them, keeping the given order
+/// where there is no dependency.
+///
+/// Self-references are ignored and references to tables outside the list don't constrain the
+/// order. Cycles are broken at the first table of the cycle in the given order; such tables
+/// rely on the foreign key check after the pull.
+fn dependency_order(tables: &[String], parents: &HashMap<&str, Vec<&str>>) -> Vec<String> {
+    let mut remaining: Vec<&str> = tables.iter().map(String::as_str).collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|&t| {
+            parents.get(t).into_iter().flatten()
+                .all(|&p| p == t || !remaining.contains(&p))
+        });
+        let next = match ready {
+            Some(i) => i,
+            None => {
+                eprintln!("Warning: foreign key cycle between tables {:?}, syncing {} first", remaining, remaining[0]);
+                0
+            }
+        };
+        ordered.push(remaining.remove(next).to_string());
+    }
+
+    ordered
+}
+
+impl DynamicSchema {
+    /// Use `resolver` for conflicts on `table` instead of last-writer-wins.
+    pub fn with_conflict_resolver(mut self, table: &str, resolver: ConflictResolver) -> Self {
+        self.resolvers.insert(table.to_string(), resolver);
+        self
+    }
 }
 
 impl SyncSchema for DynamicSchema {
@@ -692,4 +2027,500 @@ impl SyncSchema for DynamicSchema {
         self.table_info.get(table)
             .and_then(|info| info.column_types.get(col).cloned())
     }
+
+    fn conflict_resolver(&self, table: &str) -> ConflictResolver {
+        self.resolvers.get(table).cloned().unwrap_or_default()
+    }
+
+    fn parents(&self, table: &str) -> Option<Vec<&str>> {
+        self.table_info.get(table).map(|info| info.parents.iter().map(String::as_str).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(tables: &[&str], deps: &[(&'static str, &'static str)]) -> Vec<String> {
+        let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (child, parent) in deps {
+            parents.entry(child).or_default().push(parent);
+        }
+        dependency_order(&tables.iter().map(|t| t.to_string()).collect::<Vec<_>>(), &parents)
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        // Listed children-first; parents move ahead, independent tables keep their place
+        assert_eq!(
+            order(&["lines", "tags", "orders", "customers"], &[("lines", "orders"), ("orders", "customers")]),
+            vec!["tags", "customers", "orders", "lines"]
+        );
+        // Self-references and tables outside the list don't constrain the order
+        assert_eq!(
+            order(&["items", "notes"], &[("items", "items"), ("notes", "users")]),
+            vec!["items", "notes"]
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "items"));
+        assert!(glob_match("app_*", "APP_Items"));
+        assert!(glob_match("t?g*s", "tags"));
+        assert!(glob_match("*_cache", "search_cache"));
+        assert!(!glob_match("*_cache", "cache_entries"));
+        assert!(!glob_match("item", "items"));
+    }
+
+    #[test]
+    fn test_discover_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::backend::metadata::ensure_metadata(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, updated_at TEXT);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY AUTOINCREMENT, updated_at TEXT);
+             CREATE TABLE search_cache (key TEXT PRIMARY KEY, updated_at TEXT);"
+        ).unwrap();
+
+        // sqlite_sequence and the metadata tables are never included
+        assert_eq!(discover_tables(&conn, &TableFilter::default()).unwrap(), vec!["notes", "tags", "search_cache"]);
+        assert_eq!(
+            discover_tables(&conn, &TableFilter::default().exclude("*_cache")).unwrap(),
+            vec!["notes", "tags"]
+        );
+        assert_eq!(discover_tables(&conn, &TableFilter::default().include("t*")).unwrap(), vec!["tags"]);
+    }
+
+    #[test]
+    fn test_dependency_cycle_is_broken() {
+        assert_eq!(
+            order(&["a", "b", "c"], &[("a", "b"), ("b", "a"), ("c", "a")]),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    mod end_to_end {
+        use super::*;
+        use crate::backend::init_db;
+        use crate::test_util::{temp_db_path, MockTurso, TEST_SCHEMA, TEST_TABLES};
+
+        async fn device(name: &str) -> DbState {
+            let state = init_db(&temp_db_path(name)).await.unwrap();
+            exec(&state, TEST_SCHEMA).await;
+            state
+        }
+
+        async fn exec(state: &DbState, sql: &str) {
+            let conn_guard = state.get_connection().await.unwrap();
+            conn_guard.as_ref().unwrap().execute_batch(sql).unwrap();
+        }
+
+        async fn rows(state: &DbState, sql: &str) -> Vec<Row> {
+            let conn_guard = state.get_connection().await.unwrap();
+            query_rows(conn_guard.as_ref().unwrap(), sql, &[]).unwrap()
+        }
+
+        fn options() -> SyncOptions {
+            SyncOptions {
+                capture_deletes: true,
+                retry: RetryPolicy { initial_backoff: std::time::Duration::from_millis(10), ..Default::default() },
+                ..Default::default()
+            }
+        }
+
+        async fn sync(remote: &MockTurso, state: &DbState) -> SyncReport {
+            sync_profile(remote, state, DEFAULT_PROFILE).await
+        }
+
+        async fn sync_profile(remote: &MockTurso, state: &DbState, profile: &str) -> SyncReport {
+            let schema = DynamicSchema::load(state, TEST_TABLES.to_vec()).await.unwrap();
+            let options = SyncOptions { profile: profile.to_string(), ..options() };
+            let report = sync_all_with_options(&reqwest::Client::new(), state, &schema, &remote.url(), remote.token(), &options)
+                .await
+                .unwrap();
+            assert!(report.is_success(), "{:?}", report.errors());
+            report
+        }
+
+        #[tokio::test]
+        async fn test_edits_and_deletes_reach_other_device() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'hello', 1000);
+                      INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'work', 1000);").await;
+            assert_eq!(sync(&remote, &a).await.total_pushed(), 2);
+            assert_eq!(sync(&remote, &b).await.total_pulled(), 2);
+            assert_eq!(rows(&b, "SELECT title FROM notes").await, vec![vec!["hello".into()]]);
+
+            exec(&b, "UPDATE notes SET title = 'edited', updated_at = 2000 WHERE id = 'n1';
+                      DELETE FROM tags WHERE id = 't1';").await;
+            sync(&remote, &b).await;
+            sync(&remote, &a).await;
+            assert_eq!(rows(&a, "SELECT title FROM notes").await, vec![vec!["edited".into()]]);
+            assert!(rows(&a, "SELECT * FROM tags").await.is_empty());
+            assert!(remote.query("SELECT * FROM tags").unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_soft_deletes_are_synced_as_tombstones() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'hello', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            exec(&a, "UPDATE notes SET deleted_at = 2000, updated_at = 2000 WHERE id = 'n1'").await;
+            assert_eq!(sync(&remote, &a).await.tables[0].pushed, 1);
+            assert_eq!(sync(&remote, &b).await.tables[0].pulled, 1);
+            let tombstone = "SELECT id, deleted_at FROM notes";
+            assert_eq!(rows(&b, tombstone).await, vec![vec!["n1".into(), SyncValue::Integer(2000)]]);
+            assert_eq!(remote.query(tombstone).unwrap(), rows(&b, tombstone).await);
+        }
+
+        #[tokio::test]
+        async fn test_hard_deletes_go_through_the_delete_log() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'work', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            exec(&a, "DELETE FROM tags WHERE id = 't1'").await;
+            let pending = "SELECT pk, pending FROM _sync_deletes";
+            assert_eq!(rows(&a, pending).await, vec![vec![r#"["t1"]"#.into(), SyncValue::Integer(1)]]);
+            assert_eq!(sync(&remote, &a).await.tables[1].deletes_pushed, 1);
+            assert_eq!(rows(&a, pending).await, vec![vec![r#"["t1"]"#.into(), SyncValue::Integer(0)]]);
+            assert_eq!(remote.query("SELECT pk FROM _sync_deletes").unwrap(), vec![vec![r#"["t1"]"#.into()]]);
+
+            assert_eq!(sync(&remote, &b).await.tables[1].deletes_pulled, 1);
+            assert!(rows(&b, "SELECT * FROM tags").await.is_empty());
+
+            // Re-inserting the key drops its delete
+            exec(&a, "INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'again', 3000)").await;
+            assert!(rows(&a, pending).await.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_tombstones_are_purged_after_the_horizon() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            let schema = DynamicSchema::load(&a, TEST_TABLES.to_vec()).await.unwrap();
+            let purging = SyncOptions { tombstone_retention: Some(chrono::Duration::days(1)), ..options() };
+            let client = reqwest::Client::new();
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'old', 1000), ('n2', 'recent', 1000);
+                      INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'old', 1000), ('t2', 'recent', 1000);").await;
+            sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &purging).await.unwrap();
+
+            let now = chrono::Local::now().timestamp_millis();
+            exec(&a, &format!(
+                "UPDATE notes SET deleted_at = 2000, updated_at = 2000 WHERE id = 'n1';
+                 UPDATE notes SET deleted_at = {now}, updated_at = {now} WHERE id = 'n2';
+                 DELETE FROM tags WHERE id = 't1';
+                 UPDATE _sync_deletes SET deleted_at = 2000;
+                 DELETE FROM tags WHERE id = 't2';"
+            )).await;
+            let report = sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &purging).await.unwrap();
+            assert!(report.is_success(), "{:?}", report.errors());
+
+            let notes = "SELECT id FROM notes";
+            assert_eq!(rows(&a, notes).await, vec![vec!["n2".into()]]);
+            assert_eq!(remote.query(notes).unwrap(), vec![vec!["n2".into()]]);
+            let deletes = "SELECT pk FROM _sync_deletes";
+            assert_eq!(rows(&a, deletes).await, vec![vec![r#"["t2"]"#.into()]]);
+            assert_eq!(remote.query(deletes).unwrap(), vec![vec![r#"["t2"]"#.into()]]);
+            assert!(remote.query("SELECT * FROM tags").unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_concurrent_edits_converge() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'base', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            exec(&a, "UPDATE notes SET title = 'from a', updated_at = 2000 WHERE id = 'n1'").await;
+            exec(&b, "UPDATE notes SET title = 'from b', updated_at = 2001 WHERE id = 'n1'").await;
+            sync(&remote, &a).await;
+            let report = sync(&remote, &b).await;
+            assert_eq!(report.tables[0].conflicts, 1);
+            sync(&remote, &a).await;
+
+            let title = "SELECT title, sync_hlc FROM notes";
+            assert_eq!(rows(&a, title).await, rows(&b, title).await);
+            assert_eq!(rows(&a, title).await, remote.query(title).unwrap());
+        }
+
+        #[tokio::test]
+        async fn test_merge_survives_a_failed_push() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'base', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            exec(&a, "UPDATE notes SET title = 'from a', updated_at = 2000 WHERE id = 'n1'").await;
+            exec(&b, "UPDATE notes SET title = 'from b', updated_at = 2001 WHERE id = 'n1'").await;
+            sync(&remote, &a).await;
+
+            let schema = DynamicSchema::load(&b, TEST_TABLES.to_vec()).await.unwrap()
+                .with_conflict_resolver("notes", ConflictResolver::callback(|conflict| {
+                    let title = conflict.columns.iter().position(|c| c == "title").unwrap();
+                    let mut merged = conflict.local.clone();
+                    merged[title] = "merged".into();
+                    Resolution::Merged(merged)
+                }));
+            let client = reqwest::Client::new();
+            remote.fail_statement("COMMIT");
+            let report = sync_all_with_options(&client, &b, &schema, &remote.url(), remote.token(), &options()).await.unwrap();
+            assert!(!report.is_success());
+
+            let report = sync_all_with_options(&client, &b, &schema, &remote.url(), remote.token(), &options()).await.unwrap();
+            assert!(report.is_success(), "{:?}", report.errors());
+            assert_eq!(remote.query("SELECT title FROM notes").unwrap(), vec![vec!["merged".into()]]);
+        }
+
+        #[tokio::test]
+        async fn test_offline_writes_with_old_timestamps() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'work', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            // B writes offline with a clock behind the rows A pulls meanwhile
+            exec(&b, "INSERT INTO notes (id, title, updated_at) VALUES ('old', 'offline', 500);
+                      UPDATE tags SET name = 'stale', updated_at = 900 WHERE id = 't1';").await;
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('new', 'online', 5000);
+                      DELETE FROM tags WHERE id = 't1';").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+            sync(&remote, &a).await;
+
+            let ids = "SELECT id FROM notes ORDER BY id";
+            assert_eq!(rows(&a, ids).await, vec![vec!["new".into()], vec!["old".into()]]);
+            // The delete is newer than B's edit, which must not re-create the row
+            assert!(remote.query("SELECT * FROM tags").unwrap().is_empty());
+            assert!(rows(&b, "SELECT * FROM tags").await.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_profiles_keep_separate_watermarks() {
+            let (personal, team) = (MockTurso::start().await.unwrap(), MockTurso::start().await.unwrap());
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('shared', 'from team', 1000)").await;
+            sync(&team, &a).await;
+
+            // B's watermarks on its personal remote pass the team note's
+            exec(&b, "INSERT INTO notes (id, title, updated_at) VALUES ('mine', 'private', 5000)").await;
+            sync_profile(&personal, &b, "personal").await;
+            sync_profile(&personal, &b, "personal").await;
+
+            assert_eq!(sync_profile(&team, &b, "team").await.total_pulled(), 1);
+            let ids = "SELECT id FROM notes ORDER BY id";
+            assert_eq!(rows(&b, ids).await, vec![vec!["mine".into()], vec!["shared".into()]]);
+            let profiles = rows(&b, "SELECT DISTINCT profile FROM sync_status ORDER BY profile").await;
+            assert_eq!(profiles, vec![vec!["personal".into()], vec!["team".into()]]);
+        }
+
+        #[tokio::test]
+        async fn test_changes_reach_every_profile() {
+            let (personal, team) = (MockTurso::start().await.unwrap(), MockTurso::start().await.unwrap());
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'draft', 1000);
+                      INSERT INTO tags (id, name, updated_at) VALUES ('t1', 'work', 1000);").await;
+            sync_profile(&personal, &a, "personal").await;
+            sync_profile(&team, &a, "team").await;
+
+            // Edited without bumping updated_at, deleted, and written by B on the personal remote
+            exec(&a, "UPDATE notes SET title = 'edited' WHERE id = 'n1';
+                      DELETE FROM tags WHERE id = 't1';").await;
+            exec(&b, "INSERT INTO notes (id, title, updated_at) VALUES ('n2', 'from b', 2000)").await;
+            sync_profile(&personal, &b, "personal").await;
+            sync_profile(&personal, &a, "personal").await;
+
+            let report = sync_profile(&team, &a, "team").await;
+            assert_eq!((report.tables[0].pushed, report.tables[1].deletes_pushed), (2, 1));
+            let notes = "SELECT id, title FROM notes ORDER BY id";
+            assert_eq!(team.query(notes).unwrap(), vec![vec!["n1".into(), "edited".into()], vec!["n2".into(), "from b".into()]]);
+            assert!(team.query("SELECT * FROM tags").unwrap().is_empty());
+
+            // Nothing is pushed twice
+            assert_eq!(sync_profile(&team, &a, "team").await.total_pushed(), 0);
+            assert_eq!(sync_profile(&personal, &a, "personal").await.total_pushed(), 0);
+        }
+
+        #[tokio::test]
+        async fn test_interrupted_pull_resumes_in_hlc_order() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            // Each push gets a later HLC but an earlier updated_at
+            for (id, updated_at) in [("n1", 4000), ("n2", 3000), ("n3", 2000)] {
+                exec(&a, &format!("INSERT INTO notes (id, title, updated_at) VALUES ('{}', 'v1', {})", id, updated_at)).await;
+                sync(&remote, &a).await;
+            }
+
+            let cancel = CancelToken::new();
+            let on_page = cancel.clone();
+            let interrupted = SyncOptions {
+                pull_page_size: 1,
+                cancel: Some(cancel),
+                progress: Some(ProgressCallback::new(move |p| {
+                    if p.phase == SyncPhase::Pull && p.table.as_deref() == Some("notes") && p.rows == 1 {
+                        on_page.cancel();
+                    }
+                })),
+                ..options()
+            };
+            let schema = DynamicSchema::load(&b, TEST_TABLES.to_vec()).await.unwrap();
+            let report = sync_all_with_options(&reqwest::Client::new(), &b, &schema, &remote.url(), remote.token(), &interrupted)
+                .await
+                .unwrap();
+            assert!(report.cancelled);
+
+            // Written meanwhile: an edit with an old timestamp, then a newer row
+            exec(&a, "UPDATE notes SET title = 'v2', updated_at = 500 WHERE id = 'n3';
+                      INSERT INTO notes (id, title, updated_at) VALUES ('n4', 'v1', 5000);").await;
+            sync(&remote, &a).await;
+
+            let resumed = SyncOptions { pull_page_size: 1, ..options() };
+            let report = sync_all_with_options(&reqwest::Client::new(), &b, &schema, &remote.url(), remote.token(), &resumed)
+                .await
+                .unwrap();
+            assert!(report.is_success(), "{:?}", report.errors());
+            sync(&remote, &b).await;
+            let notes = "SELECT id, title FROM notes ORDER BY id";
+            assert_eq!(rows(&b, notes).await, remote.query(notes).unwrap());
+            assert_eq!(rows(&b, "SELECT title FROM notes WHERE id = 'n3'").await, vec![vec!["v2".into()]]);
+        }
+
+        #[tokio::test]
+        async fn test_late_push_with_a_lower_hlc_is_pulled() {
+            let remote = MockTurso::start().await.unwrap();
+            let (a, b) = (device("a").await, device("b").await);
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'hello', 1000)").await;
+            sync(&remote, &a).await;
+            sync(&remote, &b).await;
+
+            // Another device's push lands after B pulled past its HLC
+            let pulled = remote.query("SELECT sync_hlc FROM notes").unwrap();
+            let SyncValue::Text(pulled) = &pulled[0][0] else { panic!("{:?}", pulled) };
+            let late = Hlc { millis: Hlc::parse(pulled).unwrap().millis - 1000, counter: 0, device_id: "c".into() };
+            remote.execute_batch(&format!(
+                "INSERT INTO notes (id, title, updated_at, sync_hlc) VALUES ('late', 'offline', 500, '{}')", late
+            )).unwrap();
+            exec(&b, "UPDATE notes SET title = 'edited', updated_at = 2000 WHERE id = 'n1'").await;
+
+            let report = sync(&remote, &b).await;
+            assert_eq!((report.tables[0].pulled, report.tables[0].conflicts), (1, 0));
+            let notes = "SELECT id, title FROM notes ORDER BY id";
+            assert_eq!(rows(&b, notes).await, vec![vec!["late".into(), "offline".into()], vec!["n1".into(), "edited".into()]]);
+            assert_eq!(remote.query(notes).unwrap(), rows(&b, notes).await);
+        }
+
+        #[tokio::test]
+        async fn test_schema_drift_is_migrated() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            sync(&remote, &a).await;
+
+            exec(&a, "ALTER TABLE notes ADD COLUMN body TEXT;
+                      INSERT INTO notes (id, title, body, updated_at) VALUES ('n1', 'title', 'body', 1000);").await;
+            sync(&remote, &a).await;
+            assert_eq!(remote.query("SELECT body FROM notes").unwrap(), vec![vec!["body".into()]]);
+        }
+
+        #[tokio::test]
+        async fn test_recreated_remote_is_verified_again() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            sync(&remote, &a).await;
+
+            remote.execute_batch("DROP TABLE notes").unwrap();
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'hello', 1000)").await;
+            assert_eq!(sync(&remote, &a).await.total_pushed(), 1);
+            assert_eq!(remote.query("SELECT title FROM notes").unwrap(), vec![vec!["hello".into()]]);
+        }
+
+        #[tokio::test]
+        async fn test_applied_remote_migrations_are_not_checked_again() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            let schema = DynamicSchema::load(&a, TEST_TABLES.to_vec()).await.unwrap();
+            let migrated = SyncOptions {
+                remote_migrations: Some(Migrations::new().with(crate::Migration::sql(1, "create extra", "CREATE TABLE extra (id TEXT PRIMARY KEY)"))),
+                ..options()
+            };
+            let client = reqwest::Client::new();
+            sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &migrated).await.unwrap();
+            assert_eq!(remote.query("SELECT version FROM _sync_migrations").unwrap(), vec![vec![SyncValue::Integer(1)]]);
+
+            let requests = remote.request_count();
+            sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &options()).await.unwrap();
+            let plain = remote.request_count() - requests;
+            let requests = remote.request_count();
+            let report = sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &migrated).await.unwrap();
+            assert!(report.is_success(), "{:?}", report.errors());
+            assert_eq!(remote.request_count() - requests, plain);
+        }
+
+        #[tokio::test]
+        async fn test_transient_failures_are_retried() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            exec(&a, "INSERT INTO notes (id, title, updated_at) VALUES ('n1', 'hello', 1000)").await;
+
+            remote.fail_next(&[503, 429]);
+            assert_eq!(sync(&remote, &a).await.total_pushed(), 1);
+
+            let schema = DynamicSchema::load(&a, vec!["notes"]).await.unwrap();
+            let requests = remote.request_count();
+            let err = sync_all(&reqwest::Client::new(), &a, &schema, &remote.url(), "wrong-token").await.unwrap_err();
+            assert!(matches!(err, SyncDbError::Auth { status: 401, .. }), "{}", err);
+            assert_eq!(remote.request_count(), requests + 1, "auth failures are not retried");
+        }
+
+        #[tokio::test]
+        async fn test_local_failures_keep_their_kind() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = device("a").await;
+            let schema = DynamicSchema::load(&a, vec!["notes"]).await.unwrap();
+
+            let closed = DbState::new(temp_db_path("closed"));
+            let err = sync_all(&reqwest::Client::new(), &closed, &schema, &remote.url(), remote.token()).await.unwrap_err();
+            assert_eq!(err, SyncDbError::NotInitialized);
+
+            exec(&a, "DROP TABLE _sync_device").await;
+            let err = sync_all(&reqwest::Client::new(), &a, &schema, &remote.url(), remote.token()).await.unwrap_err();
+            assert_eq!(err.kind(), "sql", "{}", err);
+        }
+
+        #[tokio::test]
+        async fn test_strict_skips_only_dependents_of_failed_tables() {
+            let remote = MockTurso::start().await.unwrap();
+            let a = init_db(&temp_db_path("strict")).await.unwrap();
+            exec(&a, "CREATE TABLE projects (id TEXT PRIMARY KEY, name TEXT, updated_at INTEGER);
+                      CREATE TABLE tasks (id TEXT PRIMARY KEY, project_id TEXT REFERENCES projects(id), title TEXT, updated_at INTEGER);
+                      CREATE TABLE labels (id TEXT PRIMARY KEY, name TEXT, updated_at INTEGER);").await;
+            let schema = DynamicSchema::load(&a, vec!["tasks", "projects", "labels"]).await.unwrap();
+            let strict = SyncOptions { strict: true, ..options() };
+            let client = reqwest::Client::new();
+            sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &strict).await.unwrap();
+
+            remote.execute_batch("CREATE TRIGGER reject BEFORE INSERT ON projects BEGIN SELECT RAISE(ABORT, 'rejected'); END;").unwrap();
+            exec(&a, "INSERT INTO projects (id, name, updated_at) VALUES ('p1', 'home', 1000);
+                      INSERT INTO tasks (id, project_id, title, updated_at) VALUES ('k1', 'p1', 'dishes', 1000);
+                      INSERT INTO labels (id, name, updated_at) VALUES ('l1', 'urgent', 1000);").await;
+            let report = sync_all_with_options(&client, &a, &schema, &remote.url(), remote.token(), &strict).await.unwrap();
+
+            assert!(!report.is_success());
+            assert_eq!(report.errors().iter().map(|(table, _)| *table).collect::<Vec<_>>(), vec!["projects"]);
+            assert_eq!(report.skipped_tables, vec!["tasks"]);
+            assert_eq!(report.tables.iter().find(|t| t.table == "labels").unwrap().pushed, 1);
+            assert!(remote.query("SELECT * FROM tasks").unwrap().is_empty());
+        }
+    }
 }
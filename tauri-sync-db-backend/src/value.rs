@@ -0,0 +1,290 @@
+//! Typed row values
+//!
+//! `SyncValue` mirrors the five SQLite storage classes so rows keep their types
+//! on the way between the local database and Turso.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
+use serde_json::json;
+
+/// A single SQLite value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// A row of values, in the order of the selected columns.
+pub type Row = Vec<SyncValue>;
+
+impl SyncValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, SyncValue::Null)
+    }
+
+    /// Encode as plain JSON, as stored in conflict records and sync cursors.
+    /// Integers and floats stay JSON numbers, blobs become `{"base64": ...}`.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            SyncValue::Null => serde_json::Value::Null,
+            SyncValue::Integer(i) => json!(i),
+            // Non-finite floats have no JSON representation
+            SyncValue::Real(f) => serde_json::Number::from_f64(*f)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            SyncValue::Text(t) => serde_json::Value::String(t.clone()),
+            SyncValue::Blob(b) => json!({ "base64": BASE64.encode(b) }),
+        }
+    }
+
+    /// Decode a value encoded by [`to_json`](Self::to_json).
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        use serde_json::Value as Json;
+
+        match value {
+            Json::Null => SyncValue::Null,
+            Json::Bool(b) => SyncValue::Integer(*b as i64),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => SyncValue::Integer(i),
+                None => SyncValue::Real(n.as_f64().unwrap_or_default()),
+            },
+            Json::String(s) => SyncValue::Text(s.clone()),
+            Json::Object(obj) => match obj.get("base64").and_then(|b| b.as_str()) {
+                Some(b64) => match BASE64.decode(b64) {
+                    Ok(bytes) => SyncValue::Blob(bytes),
+                    Err(_) => SyncValue::Text(value.to_string()),
+                },
+                None => SyncValue::Text(value.to_string()),
+            },
+            Json::Array(_) => SyncValue::Text(value.to_string()),
+        }
+    }
+
+    /// Encode as a Hrana value: `{"type": "integer", "value": "42"}` and so on.
+    /// Integers are sent as strings so they keep their full 64-bit precision.
+    pub fn to_hrana(&self) -> serde_json::Value {
+        match self {
+            SyncValue::Null => json!({ "type": "null" }),
+            SyncValue::Integer(i) => json!({ "type": "integer", "value": i.to_string() }),
+            SyncValue::Real(f) if f.is_finite() => json!({ "type": "float", "value": f }),
+            SyncValue::Real(_) => json!({ "type": "null" }),
+            SyncValue::Text(t) => json!({ "type": "text", "value": t }),
+            SyncValue::Blob(b) => json!({ "type": "blob", "base64": BASE64_NO_PAD.encode(b) }),
+        }
+    }
+
+    /// Decode a Hrana value. Unknown shapes become NULL.
+    pub fn from_hrana(value: &serde_json::Value) -> Self {
+        let field = |name: &str| value.get(name);
+        match field("type").and_then(|t| t.as_str()) {
+            Some("integer") => match field("value") {
+                Some(serde_json::Value::String(s)) => s.parse().map(SyncValue::Integer).unwrap_or(SyncValue::Null),
+                Some(v) => v.as_i64().map(SyncValue::Integer).unwrap_or(SyncValue::Null),
+                None => SyncValue::Null,
+            },
+            Some("float") => field("value").and_then(|v| v.as_f64()).map(SyncValue::Real).unwrap_or(SyncValue::Null),
+            Some("text") => field("value").and_then(|v| v.as_str()).map(SyncValue::from).unwrap_or(SyncValue::Null),
+            Some("blob") => field("base64")
+                .and_then(|v| v.as_str())
+                .and_then(|b| BASE64_NO_PAD.decode(b.trim_end_matches('=')).ok())
+                .map(SyncValue::Blob)
+                .unwrap_or(SyncValue::Null),
+            _ => SyncValue::Null,
+        }
+    }
+
+    /// Compare two values using SQLite's ordering rules:
+    /// NULL < INTEGER/REAL (numerically) < TEXT < BLOB.
+    pub fn sqlite_cmp(&self, other: &SyncValue) -> Ordering {
+        fn class(v: &SyncValue) -> u8 {
+            match v {
+                SyncValue::Null => 0,
+                SyncValue::Integer(_) | SyncValue::Real(_) => 1,
+                SyncValue::Text(_) => 2,
+                SyncValue::Blob(_) => 3,
+            }
+        }
+
+        match (self, other) {
+            (SyncValue::Integer(x), SyncValue::Integer(y)) => x.cmp(y),
+            (SyncValue::Integer(x), SyncValue::Real(y)) => (*x as f64).total_cmp(y),
+            (SyncValue::Real(x), SyncValue::Integer(y)) => x.total_cmp(&(*y as f64)),
+            (SyncValue::Real(x), SyncValue::Real(y)) => x.total_cmp(y),
+            (SyncValue::Text(x), SyncValue::Text(y)) => x.cmp(y),
+            (SyncValue::Blob(x), SyncValue::Blob(y)) => x.cmp(y),
+            _ => class(self).cmp(&class(other)),
+        }
+    }
+}
+
+impl fmt::Display for SyncValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncValue::Null => write!(f, "NULL"),
+            SyncValue::Integer(i) => write!(f, "{}", i),
+            SyncValue::Real(r) => write!(f, "{}", r),
+            SyncValue::Text(t) => write!(f, "{}", t),
+            SyncValue::Blob(b) => write!(f, "<blob {} bytes>", b.len()),
+        }
+    }
+}
+
+impl From<Value> for SyncValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => SyncValue::Null,
+            Value::Integer(i) => SyncValue::Integer(i),
+            Value::Real(f) => SyncValue::Real(f),
+            Value::Text(t) => SyncValue::Text(t),
+            Value::Blob(b) => SyncValue::Blob(b),
+        }
+    }
+}
+
+impl From<SyncValue> for Value {
+    fn from(value: SyncValue) -> Self {
+        match value {
+            SyncValue::Null => Value::Null,
+            SyncValue::Integer(i) => Value::Integer(i),
+            SyncValue::Real(f) => Value::Real(f),
+            SyncValue::Text(t) => Value::Text(t),
+            SyncValue::Blob(b) => Value::Blob(b),
+        }
+    }
+}
+
+impl From<&str> for SyncValue {
+    fn from(value: &str) -> Self {
+        SyncValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for SyncValue {
+    fn from(value: String) -> Self {
+        SyncValue::Text(value)
+    }
+}
+
+impl From<i64> for SyncValue {
+    fn from(value: i64) -> Self {
+        SyncValue::Integer(value)
+    }
+}
+
+impl ToSql for SyncValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Borrowed(match self {
+            SyncValue::Null => ValueRef::Null,
+            SyncValue::Integer(i) => ValueRef::Integer(*i),
+            SyncValue::Real(f) => ValueRef::Real(*f),
+            SyncValue::Text(t) => ValueRef::Text(t.as_bytes()),
+            SyncValue::Blob(b) => ValueRef::Blob(b),
+        }))
+    }
+}
+
+impl FromSql for SyncValue {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Ok(match value {
+            ValueRef::Null => SyncValue::Null,
+            ValueRef::Integer(i) => SyncValue::Integer(i),
+            ValueRef::Real(f) => SyncValue::Real(f),
+            ValueRef::Text(t) => SyncValue::Text(String::from_utf8_lossy(t).into_owned()),
+            ValueRef::Blob(b) => SyncValue::Blob(b.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn samples() -> Vec<SyncValue> {
+        vec![
+            SyncValue::Null,
+            SyncValue::Integer(0),
+            SyncValue::Integer(i64::MAX),
+            SyncValue::Integer(i64::MIN),
+            SyncValue::Real(1.0),
+            SyncValue::Real(-0.1),
+            SyncValue::Real(1e300),
+            SyncValue::Text(String::new()),
+            SyncValue::Text("it's 'quoted' ✓".to_string()),
+            SyncValue::Text("42".to_string()),
+            SyncValue::Blob(Vec::new()),
+            SyncValue::Blob(vec![0, 159, 146, 150, 255]),
+        ]
+    }
+
+    #[test]
+    fn test_sqlite_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        // Untyped column, so SQLite keeps the storage class of each value
+        conn.execute("CREATE TABLE t (v)", []).unwrap();
+
+        for value in samples() {
+            conn.execute("DELETE FROM t", []).unwrap();
+            conn.execute("INSERT INTO t (v) VALUES (?1)", [&value]).unwrap();
+            let back: SyncValue = conn.query_row("SELECT v FROM t", [], |r| r.get(0)).unwrap();
+            assert_eq!(back, value);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for value in samples() {
+            let encoded = serde_json::to_string(&value.to_json()).unwrap();
+            let decoded: serde_json::Value = serde_json::from_str(&encoded).unwrap();
+            assert_eq!(SyncValue::from_json(&decoded), value, "encoded as {}", encoded);
+        }
+    }
+
+    #[test]
+    fn test_hrana_round_trip() {
+        for value in samples() {
+            let encoded = serde_json::to_string(&value.to_hrana()).unwrap();
+            let decoded: serde_json::Value = serde_json::from_str(&encoded).unwrap();
+            assert_eq!(SyncValue::from_hrana(&decoded), value, "encoded as {}", encoded);
+        }
+        // Padded base64 from other clients is accepted too
+        assert_eq!(
+            SyncValue::from_hrana(&json!({ "type": "blob", "base64": "AJ8=" })),
+            SyncValue::Blob(vec![0, 159])
+        );
+    }
+
+    #[test]
+    fn test_rusqlite_value_round_trip() {
+        for value in samples() {
+            let converted: Value = value.clone().into();
+            assert_eq!(SyncValue::from(converted), value);
+        }
+    }
+
+    #[test]
+    fn test_sqlite_ordering() {
+        let conn = Connection::open_in_memory().unwrap();
+        let values = samples();
+
+        for a in &values {
+            for b in &values {
+                let expected: i64 = conn
+                    .query_row("SELECT CASE WHEN ?1 < ?2 THEN -1 WHEN ?1 > ?2 THEN 1 ELSE 0 END", [a, b], |r| r.get(0))
+                    .unwrap();
+                // SQLite comparisons with NULL are NULL, which the CASE maps to 0
+                if a.is_null() || b.is_null() {
+                    continue;
+                }
+                assert_eq!(a.sqlite_cmp(b) as i64, expected, "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Cloud Sync Command Wrappers
 //!
-//! Frontend bindings for cloud synchronization commands.
+//! Frontend bindings for cloud synchronization commands, served by the `sync-db`
+//! plugin of `tauri-sync-db-backend`.
 
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -13,11 +14,23 @@ extern "C" {
     fn invoke(cmd: &str, args: JsValue) -> Promise;
 }
 
-/// Sync configuration structure
+/// Message of a failed command: the `message` of a serialized `SyncDbError`, or the
+/// rejection itself when it is a plain string.
+pub fn error_message(error: &JsValue) -> String {
+    js_sys::Reflect::get(error, &JsValue::from_str("message"))
+        .ok()
+        .and_then(|m| m.as_string())
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| format!("{:?}", error))
+}
+
+/// Sync configuration structure. The token itself never leaves the backend.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncConfig {
     pub url: String,
-    pub token: String,
+    pub profile: String,
+    /// Whether a token is stored; saving with an empty token keeps it.
+    pub has_token: bool,
 }
 
 /// Configure cloud synchronization
@@ -31,9 +44,9 @@ pub async fn configure_cloud_sync(url: String, token: String) -> Result<(), Stri
     let args = serde_wasm_bindgen::to_value(&Args { url, token })
         .map_err(|e| format!("Serialization error: {}", e))?;
     
-    let promise = invoke("configure_sync", args);
+    let promise = invoke("plugin:sync-db|configure_sync", args);
     let result = JsFuture::from(promise).await
-        .map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)))?;
+        .map_err(|e| error_message(&e))?;
     
     serde_wasm_bindgen::from_value(result)
         .map_err(|e| format!("Response error: {}", e))
@@ -41,9 +54,9 @@ pub async fn configure_cloud_sync(url: String, token: String) -> Result<(), Stri
 
 /// Get current sync configuration
 pub async fn get_cloud_sync_config() -> Result<Option<SyncConfig>, String> {
-    let promise = invoke("get_sync_config", JsValue::NULL);
+    let promise = invoke("plugin:sync-db|get_sync_config", JsValue::NULL);
     let result = JsFuture::from(promise).await
-        .map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)))?;
+        .map_err(|e| error_message(&e))?;
     
     serde_wasm_bindgen::from_value(result)
         .map_err(|e| format!("Response error: {}", e))
@@ -60,29 +73,146 @@ pub async fn save_cloud_sync_config(url: String, token: String) -> Result<(), St
     let args = serde_wasm_bindgen::to_value(&Args { url, token })
         .map_err(|e| format!("Serialization error: {}", e))?;
     
-    let promise = invoke("configure_sync", args);
+    let promise = invoke("plugin:sync-db|configure_sync", args);
     let result = JsFuture::from(promise).await
-        .map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)))?;
+        .map_err(|e| error_message(&e))?;
     
     serde_wasm_bindgen::from_value(result)
         .map_err(|e| format!("Response error: {}", e))
 }
 
-/// Manually trigger database sync
-pub async fn sync_cloud_db() -> Result<(), String> {
-    let promise = invoke("manual_sync", JsValue::NULL);
+/// Outcome of one table in a [`SyncReport`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableReport {
+    pub table: String,
+    pub pushed: usize,
+    pub pulled: usize,
+    pub conflicts: usize,
+    pub error: Option<String>,
+}
+
+/// Report resolved by `manual_sync`, also when some tables failed
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyncReport {
+    pub tables: Vec<TableReport>,
+    /// Tables not attempted: the rest of a cancelled run, or dependents of a failed table
+    pub skipped_tables: Vec<String>,
+    pub cancelled: bool,
+    pub duration_ms: u64,
+}
+
+impl SyncReport {
+    /// True when every table was attempted and none failed
+    pub fn is_success(&self) -> bool {
+        self.skipped_tables.is_empty() && self.tables.iter().all(|t| t.error.is_none())
+    }
+
+    /// The failed tables with their errors, for showing to the user
+    pub fn error_message(&self) -> String {
+        let mut parts: Vec<String> = self.tables.iter()
+            .filter_map(|t| t.error.as_ref().map(|e| format!("{}: {}", t.table, e)))
+            .collect();
+        if !self.skipped_tables.is_empty() {
+            parts.push(format!("skipped {}", self.skipped_tables.join(", ")));
+        }
+        parts.join("; ")
+    }
+}
+
+/// Read the result of `manual_sync`, turning a report with failed tables into an error
+pub fn sync_outcome(result: JsValue) -> Result<SyncReport, String> {
+    let report: SyncReport = serde_wasm_bindgen::from_value(result)
+        .map_err(|e| format!("Response error: {}", e))?;
+    if report.is_success() {
+        Ok(report)
+    } else {
+        Err(report.error_message())
+    }
+}
+
+/// Manually trigger database sync. Fails when any table failed.
+pub async fn sync_cloud_db() -> Result<SyncReport, String> {
+    let promise = invoke("plugin:sync-db|manual_sync", JsValue::NULL);
     let result = JsFuture::from(promise).await
-        .map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)))?;
+        .map_err(|e| error_message(&e))?;
     
-    serde_wasm_bindgen::from_value(result)
-        .map_err(|e| format!("Response error: {}", e))
+    sync_outcome(result)
 }
 
 /// Check if cloud sync is enabled for current session
 pub async fn is_cloud_sync_enabled() -> bool {
-    let promise = invoke("is_cloud_sync_enabled", JsValue::NULL);
+    let promise = invoke("plugin:sync-db|is_cloud_sync_enabled", JsValue::NULL);
     match JsFuture::from(promise).await {
         Ok(result) => serde_wasm_bindgen::from_value(result).unwrap_or(false),
         Err(_) => false
     }
 }
+
+/// A named remote the database can sync with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncProfile {
+    pub name: String,
+    pub url: String,
+}
+
+/// Configured profiles and the one synced with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncProfiles {
+    pub active: Option<String>,
+    pub profiles: Vec<SyncProfile>,
+}
+
+/// List sync profiles
+pub async fn list_sync_profiles() -> Result<SyncProfiles, String> {
+    let promise = invoke("plugin:sync-db|list_profiles", JsValue::NULL);
+    let result = JsFuture::from(promise).await
+        .map_err(|e| error_message(&e))?;
+    
+    serde_wasm_bindgen::from_value(result)
+        .map_err(|e| format!("Response error: {}", e))
+}
+
+/// Add a sync profile, or update the URL and token of an existing one
+pub async fn add_sync_profile(name: String, url: String, token: String) -> Result<(), String> {
+    #[derive(Serialize)]
+    struct Args {
+        name: String,
+        url: String,
+        token: String,
+    }
+    
+    let args = serde_wasm_bindgen::to_value(&Args { name, url, token })
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    
+    let promise = invoke("plugin:sync-db|add_profile", args);
+    JsFuture::from(promise).await
+        .map_err(|e| error_message(&e))?;
+    
+    Ok(())
+}
+
+/// Remove a sync profile
+pub async fn remove_sync_profile(name: String) -> Result<(), String> {
+    profile_command("plugin:sync-db|remove_profile", name).await
+}
+
+/// Sync with another profile from now on
+pub async fn activate_sync_profile(name: String) -> Result<(), String> {
+    profile_command("plugin:sync-db|activate_profile", name).await
+}
+
+async fn profile_command(cmd: &str, name: String) -> Result<(), String> {
+    #[derive(Serialize)]
+    struct Args {
+        name: String,
+    }
+    
+    let args = serde_wasm_bindgen::to_value(&Args { name })
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    
+    let promise = invoke(cmd, args);
+    JsFuture::from(promise).await
+        .map_err(|e| error_message(&e))?;
+    
+    Ok(())
+}
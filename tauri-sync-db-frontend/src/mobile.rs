@@ -17,16 +17,15 @@ async fn invoke_safe(cmd: &str, args: JsValue) -> Result<JsValue, String> {
         async fn invoke(cmd: &str, args: JsValue) -> Result<JsValue, JsValue>;
     }
     
-    invoke(cmd, args).await.map_err(|e| {
-        e.as_string().unwrap_or_else(|| format!("{:?}", e))
-    })
+    invoke(cmd, args).await.map_err(|e| crate::commands::error_message(&e))
 }
 
-/// Sync configuration data
+/// Sync configuration data. The token itself never leaves the backend.
 #[derive(Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct SyncConfig {
     pub url: String,
-    pub token: String,
+    pub profile: String,
+    pub has_token: bool,
 }
 
 /// Mobile sync settings form component
@@ -39,6 +38,7 @@ pub fn SyncSettingsForm(
     let message = RwSignal::new(String::new());
     let is_error = RwSignal::new(false);
     let is_configured = RwSignal::new(false);
+    let has_token = RwSignal::new(false);
     let is_syncing = RwSignal::new(false);
     let has_legacy = RwSignal::new(false);
     let is_migrating = RwSignal::new(false);
@@ -47,18 +47,18 @@ pub fn SyncSettingsForm(
     create_effect(move |_| {
         spawn_local(async move {
             // Check sync config
-            match invoke_safe("get_sync_config", JsValue::NULL).await {
+            match invoke_safe("plugin:sync-db|get_sync_config", JsValue::NULL).await {
                 Ok(result) => {
                     if let Ok(Some(c)) = serde_wasm_bindgen::from_value::<Option<SyncConfig>>(result) {
                         url.set(c.url);
-                        token.set(c.token);
+                        has_token.set(c.has_token);
                         is_configured.set(true);
                     }
                 }
                 Err(_) => {}
             }
             // Check legacy database
-            if let Ok(result) = invoke_safe("has_legacy_db", JsValue::NULL).await {
+            if let Ok(result) = invoke_safe("plugin:sync-db|has_legacy_db", JsValue::NULL).await {
                 if let Ok(has) = serde_wasm_bindgen::from_value::<bool>(result) {
                     has_legacy.set(has);
                 }
@@ -73,7 +73,8 @@ pub fn SyncSettingsForm(
         let url_val = url.get();
         let token_val = token.get();
         
-        if url_val.is_empty() || token_val.is_empty() {
+        // An empty token keeps the stored one
+        if url_val.is_empty() || (token_val.is_empty() && !has_token.get()) {
             message.set("请填写 URL 和 Token".to_string());
             is_error.set(true);
             return;
@@ -85,11 +86,12 @@ pub fn SyncSettingsForm(
                 "token": token_val,
             })).unwrap();
             
-            match invoke_safe("configure_sync", args).await {
+            match invoke_safe("plugin:sync-db|configure_sync", args).await {
                 Ok(_) => {
                     message.set("配置已保存！请重启应用以使用云同步。".to_string());
                     is_error.set(false);
                     is_configured.set(true);
+                    has_token.set(true);
                 }
                 Err(e) => {
                     message.set(format!("保存失败: {}", e));
@@ -105,7 +107,7 @@ pub fn SyncSettingsForm(
         is_syncing.set(true);
         
         spawn_local(async move {
-            match invoke_safe("manual_sync", JsValue::NULL).await {
+            match invoke_safe("plugin:sync-db|manual_sync", JsValue::NULL).await.and_then(crate::commands::sync_outcome) {
                 Ok(_) => {
                     message.set("同步成功！".to_string());
                     is_error.set(false);
@@ -125,7 +127,7 @@ pub fn SyncSettingsForm(
         is_migrating.set(true);
         
         spawn_local(async move {
-            match invoke_safe("migrate_from_legacy", JsValue::NULL).await {
+            match invoke_safe("plugin:sync-db|migrate_from_legacy", JsValue::NULL).await {
                 Ok(result) => {
                     if let Ok(msg) = serde_wasm_bindgen::from_value::<String>(result) {
                         message.set(msg);
@@ -197,7 +199,7 @@ pub fn SyncSettingsForm(
                     <label style="display: block; margin-bottom: 8px; font-weight: 500;">"Auth Token"</label>
                     <input
                         type="password"
-                        placeholder="eyJhbGciOiJFZ..."
+                        placeholder=move || if has_token.get() { "已保存，留空保持不变" } else { "eyJhbGciOiJFZ..." }
                         value=token
                         on:input=move |ev| token.set(event_target_value(&ev))
                         style="width: 100%; padding: 12px; border: 1px solid #ddd; border-radius: 8px; font-size: 14px; box-sizing: border-box;"
@@ -16,9 +16,7 @@ async fn invoke_safe(cmd: &str, args: JsValue) -> Result<JsValue, String> {
         async fn invoke(cmd: &str, args: JsValue) -> Result<JsValue, JsValue>;
     }
     
-    invoke(cmd, args).await.map_err(|e| {
-        e.as_string().unwrap_or_else(|| format!("{:?}", e))
-    })
+    invoke(cmd, args).await.map_err(|e| crate::commands::error_message(&e))
 }
 
 /// Sync button state
@@ -43,7 +41,7 @@ pub fn SyncButton() -> impl IntoView {
         sync_state.set(SyncState::Syncing);
         
         spawn_local(async move {
-            match invoke_safe("manual_sync", JsValue::NULL).await {
+            match invoke_safe("plugin:sync-db|manual_sync", JsValue::NULL).await.and_then(crate::commands::sync_outcome) {
                 Ok(_) => {
                     sync_state.set(SyncState::Success);
                     // Reset to idle after 2 seconds